@@ -0,0 +1,186 @@
+use crate::{
+    beatmap::Beatmap,
+    beats::{self, Beats, BeatsConfig, StreamDetector, StreamEvent},
+    cli::{AnalyzeOptions, ExportFormat},
+};
+use amethyst::{config::Config, utils::application_root_dir, Error};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::info;
+use std::{
+    cmp::Ordering,
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How loud the music and the clicks are in the rendered click track.
+const MUSIC_GAIN: f32 = 0.6;
+const CLICK_GAIN: f32 = 0.4;
+
+/// Finds the beats of a track and writes them as its beatmap, by default in
+/// `assets/beatmaps` where the game looks for it. The beats can also be
+/// exported as CSV or JSON, or heard over the track in a rendered click
+/// track, and their tempo is printed, to check how well they were detected.
+pub fn run(options: &AnalyzeOptions) -> amethyst::Result<()> {
+    if options.realtime {
+        return stream_in_real_time(&options.track);
+    }
+    let app_root = application_root_dir()?;
+    let assets_dir = app_root.join("assets");
+    let mut beats_config = BeatsConfig::load(app_root.join("config/beats.ron"));
+    options.apply_detector(&mut beats_config);
+    let detector = beats_config.detector.build()?;
+
+    let track = &options.track;
+    let beats = beats::load_or_find_beats(
+        &track.to_string_lossy(),
+        detector.as_ref(),
+        beats_config.sample_rate,
+    )?;
+    // Tracks of the assets are named the way the game loads them.
+    let audio = track
+        .canonicalize()
+        .ok()
+        .and_then(|track| {
+            let assets_dir = assets_dir.canonicalize().ok()?;
+            Some(track.strip_prefix(assets_dir).ok()?.to_path_buf())
+        })
+        .unwrap_or_else(|| track.clone());
+    let beatmap = Beatmap::from_beats(&audio.to_string_lossy(), &beats);
+
+    let outputs = if options.outputs.is_empty() {
+        let name = track
+            .file_stem()
+            .ok_or_else(|| Error::from_string(format!("{:?} is not a file", track)))?;
+        vec![assets_dir.join("beatmaps").join(name).with_extension("ron")]
+    } else {
+        options.outputs.clone()
+    };
+    for output in &outputs {
+        match ExportFormat::of(output) {
+            Some(ExportFormat::Csv) => write_csv(output, &beatmap.beats)?,
+            Some(ExportFormat::Json) => write_json(output, &beatmap)?,
+            Some(ExportFormat::Ron) | None => beatmap.write(output)?,
+        }
+        info!("Wrote the beats of {:?} to {:?}", track, output);
+    }
+
+    if let Some(clicks) = &options.clicks {
+        write_click_track(clicks, track, &beats)?;
+        info!("Rendered the click track of {:?} to {:?}", track, clicks);
+    }
+
+    print_tempo(track, &beats);
+    Ok(())
+}
+
+/// Writes one beat per line, after a header that the `FileDetector` skips,
+/// so the list can be corrected by hand and read back.
+fn write_csv(path: &Path, timestamps: &[f32]) -> amethyst::Result<()> {
+    let mut csv = "# time,interval\n".to_owned();
+    let mut previous = None;
+    for time in timestamps {
+        let interval = previous.map_or(0.0, |previous| time - previous);
+        csv.push_str(&format!("{:.4},{:.4}\n", time, interval));
+        previous = Some(*time);
+    }
+    fs::write(path, csv)?;
+    Ok(())
+}
+
+fn write_json(path: &Path, beatmap: &Beatmap) -> amethyst::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(beatmap)?)?;
+    Ok(())
+}
+
+/// Mixes a click on every beat over the track, as 16-bit mono WAV.
+fn write_click_track(path: &Path, track: &Path, beats: &Beats) -> amethyst::Result<()> {
+    // Beats read from the cache come without the samples.
+    let decoded;
+    let music = if beats.music.numbers.is_empty() {
+        decoded = beats::load_music(&track.to_string_lossy(), None)?;
+        &decoded
+    } else {
+        &beats.music
+    };
+    let clicks = beats::clicks(&beats.timestamps, music.sr, music.numbers.len());
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: music.sr as u32,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec)
+        .map_err(|e| Error::from_string(format!("Could not create {:?}: {}", path, e)))?;
+    for (sample, click) in music.numbers.iter().zip(&clicks) {
+        let mixed = (sample * MUSIC_GAIN + click * CLICK_GAIN)
+            .max(-1.0)
+            .min(1.0);
+        writer
+            .write_sample((mixed * f32::from(i16::max_value())) as i16)
+            .map_err(|e| Error::from_string(format!("Could not write {:?}: {}", path, e)))?;
+    }
+    writer
+        .finalize()
+        .map_err(|e| Error::from_string(format!("Could not write {:?}: {}", path, e)))
+}
+
+/// Streams the track through the beat tracker no faster than it plays, as
+/// the game would while playing it, and prints every beat as it comes with
+/// how late it is.
+fn stream_in_real_time(track: &Path) -> amethyst::Result<()> {
+    let stream = StreamDetector::default().stream(&track.to_string_lossy(), true)?;
+    let started = Instant::now();
+    println!("{}", track.display());
+    let mut lateness = vec![];
+    loop {
+        for event in stream.poll() {
+            match event {
+                StreamEvent::Beat(beat) => {
+                    let late = started.elapsed().as_secs_f32() - beat;
+                    println!("  {:8.3}s  {:5.0} ms late", beat, 1000.0 * late);
+                    lateness.push(late);
+                }
+                StreamEvent::End(duration) => {
+                    lateness.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                    println!("  ended after {:.1}s, {} beats", duration, lateness.len());
+                    if let Some(median) = lateness.get(lateness.len() / 2) {
+                        println!(
+                            "  median:  {:.0} ms late, at most {:.0} ms once the tempo is known",
+                            1000.0 * median,
+                            1000.0 * stream.latency()
+                        );
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn print_tempo(track: &Path, beats: &Beats) {
+    println!("{}", track.display());
+    match beats::tempo_stats(&beats.intervals) {
+        Some(stats) => {
+            println!("  beats:   {}", stats.beats);
+            println!("  median:  {:.1} BPM", stats.median_bpm);
+            println!("  mean:    {:.1} BPM", stats.mean_bpm);
+            println!(
+                "  range:   {:.1} to {:.1} BPM",
+                stats.slowest_bpm, stats.fastest_bpm
+            );
+            println!("  jitter:  {:.1} ms", stats.jitter_ms);
+            if beats.beats_per_bar > 0 {
+                println!(
+                    "  meter:   {} beats per bar, {} bars",
+                    beats.beats_per_bar,
+                    beats.downbeats.len()
+                );
+            }
+        }
+        None => println!("  {} beats, too few for a tempo", beats.timestamps.len()),
+    }
+}
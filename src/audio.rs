@@ -1,53 +1,176 @@
+use crate::{beats::BeatError, config::GameConfig};
 use amethyst::{
     assets::{AssetStorage, Loader},
-    audio::{output::Output, AudioSink, OggFormat, Source, SourceHandle},
+    audio::{output::Output, AudioSink, OggFormat, Source, SourceHandle, WavFormat},
     ecs::{World, WorldExt},
+    utils::application_root_dir,
 };
-use std::{iter::Cycle, vec::IntoIter};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Id of the next playlist made by `Music::new`.
+static NEXT_PLAYLIST_ID: AtomicU64 = AtomicU64::new(1);
 
 pub struct Sounds {
     pub bounce_sfx: SourceHandle,
+    pub score_sfx: SourceHandle,
 }
 
+/// The playlist of the DJ. The game starts on the menus with an empty one,
+/// which plays nothing.
+#[derive(Default)]
 pub struct Music {
-    pub music: Cycle<IntoIter<SourceHandle>>,
+    /// The tracks, played in turn and over again by the `DjSystem`.
+    pub playlist: Vec<SourceHandle>,
+    /// Number of tracks the DJ started so far, counting every loop.
+    pub started: u64,
+    /// Tells playlists apart, 0 for the empty one of the menus.
+    pub id: u64,
+}
+
+impl Music {
+    /// A new playlist, with an id no other playlist had.
+    pub fn new(playlist: Vec<SourceHandle>) -> Music {
+        Music {
+            playlist,
+            started: 0,
+            id: NEXT_PLAYLIST_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Number of tracks in the playlist.
+    pub fn tracks(&self) -> usize {
+        self.playlist.len()
+    }
+
+    /// The track the DJ plays next, `None` when the playlist is empty.
+    pub fn upcoming(&self) -> Option<&SourceHandle> {
+        if self.playlist.is_empty() {
+            None
+        } else {
+            self.playlist
+                .get(self.started as usize % self.playlist.len())
+        }
+    }
+}
+
+/// How late the speakers play what the game hands them, in seconds. It is
+/// measured by the `Calibration` state and read from
+/// `config/calibration.ron`; the beats of the `BeatTimeline` are this much
+/// later than in the beatmaps.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct AudioOffset {
+    pub seconds: f32,
+}
+
+/// Where the music is. Game time and music playback drift apart as soon as a
+/// frame runs late or time is scaled, so everything that has to follow the
+/// music reads this instead of `Time`. It is updated every frame by the
+/// `PlaybackClockSystem`.
+#[derive(Default)]
+pub struct PlaybackClock {
+    /// Index in the playlist of the playing track, `None` until the first
+    /// track starts.
+    pub track: Option<usize>,
+    /// How many times the playlist went round.
+    pub loops: u64,
+    /// Seconds played of the current track, pauses excluded.
+    pub position: f32,
+    /// Game time elapsed since the track started, minus `position`.
+    pub drift: f32,
+    pub paused: bool,
 }
 
-pub struct MusicFile<'a> {
-    pub audio_file: &'a str,
+/// The track being played.
+#[derive(Clone, Debug)]
+pub struct MusicFile {
+    /// Path of the track, relative to its directory.
+    pub audio_file: String,
+    /// The directory the track is in.
+    pub directory: PathBuf,
+    /// The `Loader` source of the directory, `None` for the assets directory.
+    pub source: Option<String>,
+    /// A beatmap file to play the track with, instead of the one in
+    /// `assets/beatmaps` or the detected beats.
+    pub beatmap: Option<PathBuf>,
 }
 
-/// Loads an ogg audio track.
+impl MusicFile {
+    /// A track of the assets directory.
+    pub fn asset(audio_file: &str) -> amethyst::Result<MusicFile> {
+        Ok(MusicFile {
+            audio_file: audio_file.to_owned(),
+            directory: application_root_dir()?.join("assets"),
+            source: None,
+            beatmap: None,
+        })
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.directory.join(&self.audio_file)
+    }
+}
+
+/// Loads an ogg or wav audio track, by its extension.
 fn load_audio_track(loader: &Loader, world: &World, file: &str) -> SourceHandle {
-    loader.load(file, OggFormat, (), &world.read_resource())
+    load_audio_track_from(loader, world, file, None)
+}
+
+fn load_audio_track_from(
+    loader: &Loader,
+    world: &World,
+    file: &str,
+    source: Option<&str>,
+) -> SourceHandle {
+    let storage = world.read_resource::<AssetStorage<Source>>();
+    let is_wav = Path::new(file)
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("wav"));
+    match (source, is_wav) {
+        (None, false) => loader.load(file, OggFormat, (), &storage),
+        (None, true) => loader.load(file, WavFormat, (), &storage),
+        (Some(source), false) => loader.load_from(file, OggFormat, source, (), &storage),
+        (Some(source), true) => loader.load_from(file, WavFormat, source, (), &storage),
+    }
 }
 
 /// Initialise audio in the world. This includes the background track and the
 /// sound effects.
 pub fn initialise_audio(world: &mut World) {
-    use crate::AUDIO_BOUNCE;
-
+    let sounds = world.read_resource::<GameConfig>().audio.clone();
     let (sound_effects, music) = {
         let loader = world.read_resource::<Loader>();
 
-        let mut sink = world.write_resource::<AudioSink>();
-        sink.set_volume(0.25); // Music is a bit loud, reduce the volume.
+        // Without an audio device there is no sink, and nothing to play on.
+        match world.try_fetch_mut::<AudioSink>() {
+            Some(mut sink) => sink.set_volume(sounds.music_volume),
+            None => warn!("Playing without music: {}", BeatError::NoOutputDevice),
+        }
 
-        let fetched = world.try_fetch::<MusicFile>();
-        let audio_file: &str = match fetched {
-            Some(music_file) => music_file.audio_file,
-            None => panic!(),
+        let tracks = match world.try_fetch::<MusicFile>() {
+            Some(track) => {
+                let source = track.source.as_ref().map(String::as_str);
+                vec![load_audio_track_from(
+                    &loader,
+                    &world,
+                    &track.audio_file,
+                    source,
+                )]
+            }
+            None => {
+                error!("Playing without music: no track was picked");
+                vec![]
+            }
         };
-        let music = vec![audio_file]
-            .iter()
-            .map(|file| load_audio_track(&loader, &world, file))
-            .collect::<Vec<_>>()
-            .into_iter()
-            .cycle();
-        let music = Music { music };
+        let music = Music::new(tracks);
 
         let sound = Sounds {
-            bounce_sfx: load_audio_track(&loader, &world, AUDIO_BOUNCE),
+            bounce_sfx: load_audio_track(&loader, &world, &sounds.bounce),
+            score_sfx: load_audio_track(&loader, &world, &sounds.score),
         };
 
         (sound, music)
@@ -59,11 +182,29 @@ pub fn initialise_audio(world: &mut World) {
     world.insert(music);
 }
 
-/// Plays the bounce sound when a ball hits a side or a paddle.
-pub fn play_bounce(sounds: &Sounds, storage: &AssetStorage<Source>, output: Option<&Output>) {
-    if let Some(ref output) = output.as_ref() {
+/// Plays the bounce sound when a ball hits a side or a paddle. Without sounds
+/// or an audio output, as in the headless simulation, nothing is played.
+pub fn play_bounce(
+    sounds: Option<&Sounds>,
+    storage: &AssetStorage<Source>,
+    output: Option<&Output>,
+) {
+    if let (Some(sounds), Some(output)) = (sounds, output) {
         if let Some(sound) = storage.get(&sounds.bounce_sfx) {
             output.play_once(sound, 1.0);
         }
     }
 }
+
+/// Plays the score sound when a paddle misses the ball.
+pub fn play_score(
+    sounds: Option<&Sounds>,
+    storage: &AssetStorage<Source>,
+    output: Option<&Output>,
+) {
+    if let (Some(sounds), Some(output)) = (sounds, output) {
+        if let Some(sound) = storage.get(&sounds.score_sfx) {
+            output.play_once(sound, 1.0);
+        }
+    }
+}
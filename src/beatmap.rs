@@ -0,0 +1,145 @@
+use crate::beats::{
+    self, median_bpm, BeatError, Beats, BeatsConfig, DetectorKind, GridDetector, Tempo,
+};
+use amethyst::{
+    assets::{Asset, Handle, ProcessingState},
+    ecs::VecStorage,
+    error::Error,
+};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// How long the beats of a fixed tempo last, longer than any track. They
+/// start over with the track when it loops.
+const FIXED_TEMPO_SECONDS: f32 = 3600.0;
+
+/// A named part of a track, like a chorus or a break.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Section {
+    pub time: f32,
+    pub name: String,
+}
+
+/// The rhythm of a track. Beatmaps live in `assets/beatmaps`, named after
+/// the track they describe, and take precedence over beat detection, so
+/// songs that are detected badly can be mapped by hand.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Beatmap {
+    /// Path of the track, relative to the assets directory.
+    pub audio: String,
+    pub bpm: f32,
+    /// Seconds added to every timestamp of the map.
+    #[serde(default)]
+    pub offset: f32,
+    /// Beat timestamps, in seconds from the start of the track.
+    pub beats: Vec<f32>,
+    #[serde(default)]
+    pub downbeats: Option<Vec<f32>>,
+    #[serde(default)]
+    pub sections: Vec<Section>,
+    /// Length of the track in seconds, so that the beats carry on into the
+    /// next track or loop in time. Without it they stop at the last beat.
+    #[serde(default)]
+    pub duration: Option<f32>,
+}
+
+impl Asset for Beatmap {
+    const NAME: &'static str = "beat_bouncer::Beatmap";
+    type Data = Self;
+    type HandleStorage = VecStorage<Handle<Self>>;
+}
+
+impl From<Beatmap> for Result<ProcessingState<Beatmap>, Error> {
+    fn from(beatmap: Beatmap) -> Result<ProcessingState<Beatmap>, Error> {
+        Ok(ProcessingState::Loaded(beatmap))
+    }
+}
+
+impl Beatmap {
+    /// Builds a beatmap out of detected beats.
+    pub fn from_beats(audio: &str, beats: &Beats) -> Beatmap {
+        Beatmap {
+            audio: audio.to_owned(),
+            bpm: median_bpm(&beats.intervals),
+            offset: 0.0,
+            beats: beats.timestamps.clone(),
+            downbeats: if beats.downbeats.is_empty() {
+                None
+            } else {
+                Some(beats.downbeats.clone())
+            },
+            sections: vec![],
+            duration: if beats.duration > 0.0 {
+                Some(beats.duration)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Finds the beats of the track at `path`, `audio` in the assets, with
+    /// the detector of the configuration. When they can't be found they are
+    /// put on a grid at the estimated tempo of the track, and when the track
+    /// can't even be decoded at the fallback tempo, so that there is always
+    /// something to play to. The analysis is cached next to the track, so
+    /// only the first run on a track is slow.
+    pub fn detect(audio: &str, path: &Path, config: &BeatsConfig) -> Beatmap {
+        let filename = path.to_string_lossy();
+        let detected = config.detector.build().and_then(|detector| {
+            beats::load_or_find_beats(&filename, detector.as_ref(), config.sample_rate)
+        });
+        let e = match detected {
+            Ok(beats) => return Beatmap::from_beats(audio, &beats),
+            Err(e) => e,
+        };
+        error!("Could not find the beats of {}: {}", filename, e);
+
+        // Other files, like the beats of the `File` detector, may be missing
+        // while the track is fine.
+        let decodable = match (&config.detector, &e) {
+            (DetectorKind::Grid, _) => false,
+            (_, BeatError::Io { file, .. }) | (_, BeatError::Decode { file, .. }) => {
+                *file != filename
+            }
+            _ => true,
+        };
+        if decodable {
+            let grid = GridDetector::default();
+            match beats::load_or_find_beats(&filename, &grid, config.sample_rate) {
+                Ok(beats) => {
+                    let beatmap = Beatmap::from_beats(audio, &beats);
+                    warn!("Playing on a grid at {:.1} BPM", beatmap.bpm);
+                    return beatmap;
+                }
+                Err(e) => error!("Could not estimate the tempo of {}: {}", filename, e),
+            }
+        }
+        warn!("Playing at a fixed {} BPM", config.fallback_bpm);
+        Beatmap::fixed_tempo(audio, config.fallback_bpm)
+    }
+
+    /// Evenly spaced beats at `bpm`, for a track whose beats couldn't be
+    /// found. The game stays playable, if not in time with the music.
+    pub fn fixed_tempo(audio: &str, bpm: f32) -> Beatmap {
+        let tempo = Tempo {
+            bpm,
+            confidence: 0.0,
+            phase: 0.0,
+        };
+        Beatmap {
+            audio: audio.to_owned(),
+            bpm,
+            offset: 0.0,
+            beats: tempo.grid(FIXED_TEMPO_SECONDS),
+            downbeats: None,
+            sections: vec![],
+            duration: None,
+        }
+    }
+
+    /// Beat timestamps with the offset of the map applied.
+    pub fn beat_times<'a>(&'a self) -> impl Iterator<Item = f32> + 'a {
+        self.beats.iter().map(move |beat| beat + self.offset)
+    }
+}
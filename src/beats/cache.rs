@@ -0,0 +1,136 @@
+use super::{find_beats, median_bpm, BeatDetector, BeatError, Beats, Music};
+use log::{info, warn};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Analysed beats as they are stored next to the track, in
+/// `<track>.beats.ron`. They are only reused when both the track and the
+/// detector that analysed it, and the rate it was analysed at, are unchanged.
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedBeats {
+    /// Hash of the bytes of the track.
+    hash: u64,
+    detector: String,
+    version: u32,
+    parameters: String,
+    /// Rate the track was resampled to for the analysis. Beats cached before
+    /// tracks were resampled were found at the rate of the track.
+    #[serde(default)]
+    sample_rate: Option<usize>,
+    sr: usize,
+    /// Length of the track, in seconds.
+    duration: f32,
+    timestamps: Vec<f32>,
+    intervals: Vec<f32>,
+    /// Missing from the beats cached before bars were found, which are then
+    /// analysed again.
+    #[serde(default)]
+    downbeats: Option<Vec<f32>>,
+    #[serde(default)]
+    beats_per_bar: usize,
+}
+
+impl CachedBeats {
+    fn matches(&self, hash: u64, detector: &dyn BeatDetector, sample_rate: Option<usize>) -> bool {
+        self.hash == hash
+            && self.detector == detector.name()
+            && self.version == detector.version()
+            && self.parameters == detector.parameters()
+            && self.sample_rate == sample_rate
+            && self.downbeats.is_some()
+    }
+}
+
+/// Finds the beats of a track like `find_beats`, but reuses the previous
+/// analysis when there is one for the same track, detector and sample rate.
+/// Beats read from the cache come without the decoded samples and the click
+/// track.
+pub fn load_or_find_beats(
+    filename: &str,
+    detector: &dyn BeatDetector,
+    sample_rate: Option<usize>,
+) -> Result<Beats, BeatError> {
+    let bytes = fs::read(filename).map_err(|e| BeatError::io(filename, e))?;
+    let hash = hash_bytes(&bytes);
+    let cache_file = cache_path(filename);
+
+    if let Some(cached) = read_cache(&cache_file) {
+        if cached.matches(hash, detector, sample_rate) {
+            info!("Reusing the beats of {} from {}", filename, cache_file);
+            return Ok(Beats {
+                music: Music {
+                    numbers: vec![],
+                    sr: cached.sr,
+                },
+                timestamps: cached.timestamps,
+                clicks: vec![],
+                intervals: cached.intervals,
+                downbeats: cached.downbeats.unwrap_or_default(),
+                beats_per_bar: cached.beats_per_bar,
+                duration: cached.duration,
+            });
+        }
+    }
+
+    info!(
+        "Analysing {} with the {} detector",
+        filename,
+        detector.name()
+    );
+    let beats = find_beats(filename, detector, sample_rate)?;
+    let cached = CachedBeats {
+        hash,
+        detector: detector.name().to_owned(),
+        version: detector.version(),
+        parameters: detector.parameters(),
+        sample_rate,
+        sr: beats.music.sr,
+        duration: beats.duration,
+        timestamps: beats.timestamps.clone(),
+        intervals: beats.intervals.clone(),
+        downbeats: Some(beats.downbeats.clone()),
+        beats_per_bar: beats.beats_per_bar,
+    };
+    if let Err(e) = write_cache(&cache_file, &cached) {
+        warn!("Could not cache the beats in {}: {}", cache_file, e);
+    }
+    Ok(beats)
+}
+
+/// The length in seconds and the tempo of a track, as of its last analysis,
+/// if it was ever analysed. The cache isn't checked against the track, so
+/// this is quick enough for listing many tracks.
+pub fn cached_summary(filename: &str) -> Option<(f32, f32)> {
+    let cached = read_cache(&cache_path(filename))?;
+    Some((cached.duration, median_bpm(&cached.intervals)))
+}
+
+fn cache_path(filename: &str) -> String {
+    format!("{}.beats.ron", filename)
+}
+
+fn read_cache(path: &str) -> Option<CachedBeats> {
+    let contents = fs::read_to_string(path).ok()?;
+    match ron::de::from_str(&contents) {
+        Ok(cached) => Some(cached),
+        Err(e) => {
+            warn!("Ignoring the unreadable beat cache {}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn write_cache(path: &str, cached: &CachedBeats) -> amethyst::Result<()> {
+    let contents = ron::ser::to_string_pretty(cached, PrettyConfig::default())?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` it is stable across Rust releases,
+/// which matters for hashes that end up on disk.
+pub(super) fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
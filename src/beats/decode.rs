@@ -0,0 +1,157 @@
+use super::{BeatError, Music};
+use rodio::{Decoder, Source};
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+};
+
+/// How much of the end of an Ogg file is searched for its last page.
+const OGG_TAIL: u64 = 64 * 1024;
+
+/// Zero crossings of the resampling filter on each side of a sample. More
+/// keeps more of the treble at the cost of a slower resampling.
+const SINC_ZEROS: f64 = 8.0;
+
+/// Decodes an Ogg Vorbis, WAV or FLAC track into mono samples, with the
+/// decoders rodio already uses for playback. The channels are averaged, and
+/// the samples are resampled to `sample_rate` if one is given, or else keep
+/// the rate of the track.
+pub fn load_music(filename: &str, sample_rate: Option<usize>) -> Result<Music, BeatError> {
+    let (decoder, channels, sr) = open_music(filename)?;
+    let samples = decoder.collect::<Vec<i16>>();
+    let numbers = downmix(&samples, channels);
+    if numbers.is_empty() {
+        return Err(BeatError::Decode {
+            file: filename.to_owned(),
+            reason: "no samples".to_owned(),
+        });
+    }
+    Ok(match sample_rate {
+        Some(target) if target != sr => Music {
+            numbers: resample(&numbers, sr, target),
+            sr: target,
+        },
+        _ => Music { numbers, sr },
+    })
+}
+
+/// Opens the decoder of a track, along with its number of channels and its
+/// sample rate, to read its interleaved samples as they are needed.
+pub(super) fn open_music(
+    filename: &str,
+) -> Result<(Decoder<BufReader<File>>, usize, usize), BeatError> {
+    let file = File::open(filename).map_err(|e| BeatError::io(filename, e))?;
+    let decoder = Decoder::new(BufReader::new(file)).map_err(|e| BeatError::Decode {
+        file: filename.to_owned(),
+        reason: format!("{:?}", e),
+    })?;
+    let channels = decoder.channels() as usize;
+    let sr = decoder.sample_rate() as usize;
+    if channels == 0 || sr == 0 {
+        return Err(BeatError::Decode {
+            file: filename.to_owned(),
+            reason: format!("{} channels at {} Hz", channels, sr),
+        });
+    }
+    Ok((decoder, channels, sr))
+}
+
+/// Length of a track in seconds, without decoding it: WAV and FLAC headers
+/// tell it, and for Ogg Vorbis it is the position of the last page.
+pub fn track_duration(filename: &str) -> Option<f32> {
+    let (decoder, _, sr) = open_music(filename).ok()?;
+    if let Some(duration) = decoder.total_duration() {
+        return Some(duration.as_secs_f32());
+    }
+    let samples = last_ogg_granule(filename)?;
+    Some(samples as f32 / sr as f32)
+}
+
+/// The granule position of the last page of an Ogg file, which for Vorbis is
+/// the number of samples per channel up to the end of the track.
+fn last_ogg_granule(filename: &str) -> Option<u64> {
+    let mut file = File::open(filename).ok()?;
+    let len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(OGG_TAIL)))
+        .ok()?;
+    let mut tail = vec![];
+    file.read_to_end(&mut tail).ok()?;
+    // A page starts with "OggS", a version and a header type, then the
+    // granule position in little endian.
+    let page = tail.windows(4).rposition(|bytes| bytes == b"OggS")?;
+    let granule = tail.get(page + 6..page + 14)?;
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(granule);
+    Some(u64::from_le_bytes(bytes))
+}
+
+/// Averages the channels of interleaved samples, scaled to -1..1.
+pub(super) fn downmix(samples: &[i16], channels: usize) -> Vec<f32> {
+    samples
+        .chunks(channels)
+        .map(|frame| {
+            frame
+                .iter()
+                .map(|&sample| f32::from(sample) / f32::from(i16::max_value()))
+                .sum::<f32>()
+                / channels as f32
+        })
+        .collect()
+}
+
+/// Resamples from `from` Hz to `to` Hz with a Hann-windowed sinc filter.
+/// When going down, the filter cuts at the new Nyquist frequency so the
+/// treble that no longer fits doesn't fold back as noise.
+fn resample(samples: &[f32], from: usize, to: usize) -> Vec<f32> {
+    let ratio = to as f64 / from as f64;
+    let cutoff = ratio.min(1.0);
+    // Half the width of the filter, in samples of the input.
+    let radius = SINC_ZEROS / cutoff;
+    let len = (samples.len() as f64 * ratio).round() as usize;
+    (0..len)
+        .map(|i| {
+            let centre = i as f64 / ratio;
+            let first = (centre - radius).ceil().max(0.0) as usize;
+            let last = ((centre + radius).floor() as usize).min(samples.len() - 1);
+            (first..=last)
+                .map(|j| {
+                    let x = j as f64 - centre;
+                    let window = 0.5 + 0.5 * (PI * x / radius).cos();
+                    f64::from(samples[j]) * cutoff * sinc(cutoff * x) * window
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample_keeps_the_length_in_seconds() {
+        let second = vec![0.0; 44_100];
+        assert_eq!(resample(&second, 44_100, 22_050).len(), 22_050);
+        assert_eq!(resample(&second, 44_100, 48_000).len(), 48_000);
+        assert_eq!(resample(&second[..1000], 44_100, 22_050).len(), 500);
+    }
+
+    #[test]
+    fn resample_keeps_a_constant_signal() {
+        let ones = vec![1.0; 4410];
+        let resampled = resample(&ones, 44_100, 22_050);
+        // Away from the edges, where the filter runs out of samples.
+        for sample in &resampled[100..resampled.len() - 100] {
+            assert!((sample - 1.0).abs() < 0.01, "{}", sample);
+        }
+    }
+}
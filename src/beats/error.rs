@@ -0,0 +1,66 @@
+use std::{error, fmt, io};
+
+/// Why the beats of a track could not be found or played. The game falls
+/// back to a fixed tempo on any of them, so only `analyze` stops on one.
+#[derive(Debug)]
+pub enum BeatError {
+    /// A Python module the detector needs could not be imported.
+    MissingPythonModule { module: String, reason: String },
+    /// The detector was not compiled in.
+    DetectorUnavailable(String),
+    /// A file could not be read.
+    Io { file: String, error: io::Error },
+    /// The track could not be decoded, or holds no audio.
+    Decode { file: String, reason: String },
+    /// The detector failed while analysing the track.
+    Detector {
+        detector: &'static str,
+        reason: String,
+    },
+    /// The detector found too few beats to follow.
+    NoBeatsFound { detector: &'static str },
+    /// There is no audio device to play on.
+    NoOutputDevice,
+}
+
+impl BeatError {
+    pub(super) fn io(file: &str, error: io::Error) -> BeatError {
+        BeatError::Io {
+            file: file.to_owned(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for BeatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BeatError::MissingPythonModule { module, reason } => write!(
+                f,
+                "the Python module `{}` could not be imported: {}",
+                module, reason
+            ),
+            BeatError::DetectorUnavailable(reason) => write!(f, "{}", reason),
+            BeatError::Io { file, error } => write!(f, "could not read {}: {}", file, error),
+            BeatError::Decode { file, reason } => {
+                write!(f, "could not decode {}: {}", file, reason)
+            }
+            BeatError::Detector { detector, reason } => {
+                write!(f, "the {} detector failed: {}", detector, reason)
+            }
+            BeatError::NoBeatsFound { detector } => {
+                write!(f, "the {} detector found no beats to follow", detector)
+            }
+            BeatError::NoOutputDevice => write!(f, "there is no audio output device"),
+        }
+    }
+}
+
+impl error::Error for BeatError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            BeatError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use super::{
+    load_music,
+    native::{spectral_flux, spectrogram, FRAME_SIZE, HOP_SIZE},
+    BeatError, Music,
+};
+use std::{
+    cmp::Ordering,
+    f32::INFINITY,
+    sync::{
+        mpsc::{self, Receiver},
+        Mutex,
+    },
+    thread,
+};
+
+/// Upper edges of the bass and mid bands, in Hz. The highs are the rest.
+const BASS_EDGE: f32 = 250.0;
+const MID_EDGE: f32 = 4000.0;
+/// Each feature is scaled so that this fraction of the frames of the track
+/// are below 1, and the rest are capped at 1. A single loud hit then doesn't
+/// squash the whole track.
+const NORMALISE_PERCENTILE: f32 = 0.95;
+
+/// How the playing track sounds at one moment, every value from 0 for
+/// silence to 1 for the loud parts of the track.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FeatureFrame {
+    /// How sharply something new starts, like a drum hit or a note.
+    pub onset: f32,
+    /// Loudness.
+    pub rms: f32,
+    /// Energy below 250 Hz: kicks and bass.
+    pub bass: f32,
+    /// Energy from 250 Hz to 4 kHz: voices and most instruments.
+    pub mid: f32,
+    /// Energy above 4 kHz: cymbals, hi-hats and hiss.
+    pub high: f32,
+}
+
+/// The features of the playing track, frame by frame, looked up by the
+/// position of the `PlaybackClock` on the track. Empty until a track is
+/// analysed, and then every lookup is silence.
+#[derive(Clone, Debug, Default)]
+pub struct MusicFeatures {
+    /// Frames per second of the track.
+    pub frame_rate: f32,
+    pub frames: Vec<FeatureFrame>,
+}
+
+impl MusicFeatures {
+    /// The features at `time` seconds into the track, interpolated between
+    /// the two closest frames. Silence outside of the track.
+    pub fn at(&self, time: f32) -> FeatureFrame {
+        let position = time * self.frame_rate;
+        if position < 0.0 || position.is_nan() {
+            return FeatureFrame::default();
+        }
+        let index = position.floor() as usize;
+        let before = match self.frames.get(index) {
+            Some(before) => before,
+            None => return FeatureFrame::default(),
+        };
+        let after = self.frames.get(index + 1).unwrap_or(before);
+        let t = position - index as f32;
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        FeatureFrame {
+            onset: mix(before.onset, after.onset),
+            rms: mix(before.rms, after.rms),
+            bass: mix(before.bass, after.bass),
+            mid: mix(before.mid, after.mid),
+            high: mix(before.high, after.high),
+        }
+    }
+}
+
+/// Decodes the track in `filename` and extracts its features, at
+/// `sample_rate` if one is given.
+pub fn find_features(
+    filename: &str,
+    sample_rate: Option<usize>,
+) -> Result<MusicFeatures, BeatError> {
+    Ok(extract_features(&load_music(filename, sample_rate)?))
+}
+
+/// The features of a track, extracted in the background so that the game
+/// doesn't wait for the whole track to be decoded. Dropping the job doesn't
+/// stop the extraction: it runs to the end, and its features are thrown
+/// away.
+pub struct FeaturesJob {
+    receiver: Mutex<Receiver<Result<MusicFeatures, BeatError>>>,
+}
+
+impl FeaturesJob {
+    /// Starts extracting the features of the track in `filename`, at
+    /// `sample_rate` if one is given.
+    pub fn start(filename: &str, sample_rate: Option<usize>) -> FeaturesJob {
+        let filename = filename.to_owned();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // Nobody waits for them anymore when the job was dropped.
+            let _ = sender.send(find_features(&filename, sample_rate));
+        });
+        FeaturesJob::from_receiver(receiver)
+    }
+
+    /// A job whose features are extracted elsewhere and sent to `receiver`.
+    pub(super) fn from_receiver(
+        receiver: Receiver<Result<MusicFeatures, BeatError>>,
+    ) -> FeaturesJob {
+        FeaturesJob {
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// The features once they are extracted, or why they couldn't be,
+    /// without waiting. None while the extraction runs, and after it was
+    /// polled once.
+    pub fn poll(&self) -> Option<Result<MusicFeatures, BeatError>> {
+        self.receiver.lock().ok()?.try_recv().ok()
+    }
+}
+
+/// The onset strength, loudness and band energies of mono samples, one frame
+/// every `HOP_SIZE` samples, on the frames of the native detector.
+pub fn extract_features(music: &Music) -> MusicFeatures {
+    if music.numbers.is_empty() || music.sr == 0 {
+        return MusicFeatures::default();
+    }
+    let spectrogram = spectrogram(&music.numbers);
+    let onset = spectral_flux(&spectrogram);
+
+    let hz_per_bin = music.sr as f32 / FRAME_SIZE as f32;
+    let band = |low: f32, high: f32| {
+        let first = (low / hz_per_bin).ceil() as usize;
+        let last = ((high / hz_per_bin).ceil() as usize).min(FRAME_SIZE / 2 + 1);
+        spectrogram
+            .iter()
+            .map(|frame| {
+                let bins = &frame[first.min(last)..last];
+                if bins.is_empty() {
+                    0.0
+                } else {
+                    bins.iter().map(|m| m * m).sum::<f32>() / bins.len() as f32
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let bass = band(0.0, BASS_EDGE);
+    let mid = band(BASS_EDGE, MID_EDGE);
+    let high = band(MID_EDGE, INFINITY);
+    let rms = (0..spectrogram.len())
+        .map(|frame| {
+            let centre = frame * HOP_SIZE;
+            let start = centre
+                .saturating_sub(FRAME_SIZE / 2)
+                .min(music.numbers.len());
+            let end = (centre + FRAME_SIZE / 2).min(music.numbers.len());
+            let window = &music.numbers[start..end];
+            if window.is_empty() {
+                0.0
+            } else {
+                (window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32).sqrt()
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let (onset, rms, bass, mid, high) = (
+        normalise(onset),
+        normalise(rms),
+        normalise(bass),
+        normalise(mid),
+        normalise(high),
+    );
+    let frames = (0..spectrogram.len())
+        .map(|i| FeatureFrame {
+            onset: onset[i],
+            rms: rms[i],
+            bass: bass[i],
+            mid: mid[i],
+            high: high[i],
+        })
+        .collect();
+    MusicFeatures {
+        frame_rate: music.sr as f32 / HOP_SIZE as f32,
+        frames,
+    }
+}
+
+/// Scales the values by their `NORMALISE_PERCENTILE`, capped at 1. All zeros
+/// when the values are.
+fn normalise(mut values: Vec<f32>) -> Vec<f32> {
+    if values.is_empty() {
+        return values;
+    }
+    let mut sorted = values.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let index = ((sorted.len() as f32 * NORMALISE_PERCENTILE) as usize).min(sorted.len() - 1);
+    let mut scale = sorted[index];
+    if scale <= 0.0 || scale.is_nan() {
+        // Mostly silence: the loudest frame sets the scale instead.
+        scale = sorted[sorted.len() - 1];
+    }
+    for value in values.iter_mut() {
+        *value = if scale > 0.0 {
+            (*value / scale).min(1.0)
+        } else {
+            0.0
+        };
+    }
+    values
+}
@@ -0,0 +1,54 @@
+use super::{estimate_tempo, BeatDetector, BeatError, Music};
+use log::{info, warn};
+
+/// Below this confidence the grid is unlikely to follow the music.
+const LOW_CONFIDENCE: f32 = 0.1;
+
+/// Puts the beats on an even grid at the estimated tempo of the track. It
+/// can't follow a tempo that drifts, but never loses the beat either, so it
+/// is what the game falls back to when the other detectors fail.
+#[derive(Clone, Debug)]
+pub struct GridDetector {
+    /// The tempo of the grid is estimated once over the whole track, and
+    /// of the tempos that fit it, the nearest to this one wins. It decides
+    /// whether the grid has a beat on every beat of the music, on every
+    /// other one or twice per beat.
+    pub start_bpm: f32,
+}
+
+impl Default for GridDetector {
+    fn default() -> Self {
+        GridDetector { start_bpm: 120.0 }
+    }
+}
+
+impl BeatDetector for GridDetector {
+    fn name(&self) -> &'static str {
+        "grid"
+    }
+
+    fn parameters(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn detect(&self, music: &Music) -> Result<Vec<f32>, BeatError> {
+        let tempo = estimate_tempo(music, self.start_bpm).ok_or(BeatError::NoBeatsFound {
+            detector: self.name(),
+        })?;
+        if tempo.confidence < LOW_CONFIDENCE {
+            warn!(
+                "The tempo of {:.1} BPM is a guess, the track has little pulse",
+                tempo.bpm
+            );
+        }
+        info!(
+            "Beats every {:.3}s from {:.3}s, at {:.1} BPM with a confidence of {:.2}",
+            60.0 / tempo.bpm,
+            tempo.phase,
+            tempo.bpm,
+            tempo.confidence
+        );
+        let duration = music.numbers.len() as f32 / music.sr as f32;
+        Ok(tempo.grid(duration))
+    }
+}
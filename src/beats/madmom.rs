@@ -0,0 +1,66 @@
+use super::{BeatDetector, BeatError, Music};
+use cpython::{PyDict, PyModule, PyResult, Python};
+
+/// Finds the beats with madmom's RNN beat processor. Needs an embedded
+/// Python with `madmom` and `numpy` installed.
+#[derive(Clone, Debug, Default)]
+pub struct MadmomDetector;
+
+impl BeatDetector for MadmomDetector {
+    fn name(&self) -> &'static str {
+        "madmom"
+    }
+
+    fn detect(&self, music: &Music) -> Result<Vec<f32>, BeatError> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let madmom = import(py, "madmom")?;
+        let numpy = import(py, "numpy")?;
+        track_beats(py, &madmom, &numpy, music).map_err(|e| BeatError::Detector {
+            detector: self.name(),
+            reason: format!("{:?}", e),
+        })
+    }
+}
+
+fn import(py: Python, module: &str) -> Result<PyModule, BeatError> {
+    py.import(module)
+        .map_err(|e| BeatError::MissingPythonModule {
+            module: module.to_owned(),
+            reason: format!("{:?}", e),
+        })
+}
+
+fn track_beats(
+    py: Python,
+    madmom: &PyModule,
+    numpy: &PyModule,
+    music: &Music,
+) -> PyResult<Vec<f32>> {
+    let locals = PyDict::new(py);
+    locals.set_item(py, "madmom", madmom)?;
+    locals.set_item(py, "np", numpy)?;
+    locals.set_item(py, "music", &music.numbers)?;
+    locals.set_item(py, "sr", music.sr)?;
+    locals.set_item(py, "fps", 50)?;
+    let proc = py.eval(
+        "madmom.features.beats.DBNBeatTrackingProcessor(fps=fps)",
+        None,
+        Some(&locals),
+    )?;
+    locals.set_item(py, "proc", &proc)?;
+    let signal = py.eval(
+        "madmom.audio.signal.Signal(np.array(music, dtype=np.float32), sample_rate=sr)",
+        None,
+        Some(&locals),
+    )?;
+    locals.set_item(py, "signal", &signal)?;
+    let act = py.eval(
+        "madmom.features.beats.RNNBeatProcessor()(signal)",
+        None,
+        Some(&locals),
+    )?;
+    locals.set_item(py, "act", &act)?;
+    py.eval("proc(act)", None, Some(&locals))?
+        .extract::<Vec<f32>>(py)
+}
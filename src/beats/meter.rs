@@ -0,0 +1,97 @@
+use super::Music;
+use std::f32::consts::PI;
+
+/// Meters tried, in beats per bar. On a tie the first one wins, as most
+/// music is in 4/4.
+const METERS: [usize; 2] = [4, 3];
+/// Seconds after each beat in which its bass is measured.
+const ACCENT_WINDOW: f32 = 0.1;
+/// Cutoff of the low-pass filter the bass is measured through, in Hz.
+const BASS_CUTOFF: f32 = 150.0;
+
+/// How the beats of a track group into bars.
+#[derive(Clone, Debug)]
+pub struct Meter {
+    pub beats_per_bar: usize,
+    /// Index of the first downbeat among the beats.
+    pub first_downbeat: usize,
+}
+
+impl Meter {
+    /// The beats that start a bar.
+    pub fn downbeats(&self, timestamps: &[f32]) -> Vec<f32> {
+        timestamps
+            .iter()
+            .skip(self.first_downbeat)
+            .step_by(self.beats_per_bar)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Guesses the meter of a track from how much bass each of its beats
+/// carries: kicks and bass notes tend to come down hardest at the start of a
+/// bar. `None` without the samples, or with too few beats to tell.
+pub fn find_meter(music: &Music, timestamps: &[f32]) -> Option<Meter> {
+    let longest = METERS.iter().cloned().max().unwrap_or(1);
+    if music.numbers.is_empty() || music.sr == 0 || timestamps.len() < 2 * longest {
+        return None;
+    }
+    let bass = low_pass(&music.numbers, music.sr as f32);
+    let window = (ACCENT_WINDOW * music.sr as f32) as usize;
+    let accents = timestamps
+        .iter()
+        .map(|time| {
+            let start = ((time * music.sr as f32) as usize).min(bass.len());
+            let end = (start + window).min(bass.len());
+            bass[start..end].iter().map(|s| s * s).sum::<f32>() / window as f32
+        })
+        .collect::<Vec<_>>();
+    let mean = accents.iter().sum::<f32>() / accents.len() as f32;
+    if mean <= 0.0 || mean.is_nan() {
+        return None;
+    }
+
+    // How much louder the beats at the phase of the bar are than the others.
+    let contrast = |beats_per_bar: usize, phase: usize| {
+        let (mut on, mut on_count, mut off, mut off_count) = (0.0, 0, 0.0, 0);
+        for (i, accent) in accents.iter().enumerate() {
+            if i % beats_per_bar == phase {
+                on += accent;
+                on_count += 1;
+            } else {
+                off += accent;
+                off_count += 1;
+            }
+        }
+        (on / on_count as f32 - off / off_count as f32) / mean
+    };
+    let mut best: Option<(f32, Meter)> = None;
+    for &beats_per_bar in METERS.iter() {
+        for phase in 0..beats_per_bar {
+            let score = contrast(beats_per_bar, phase);
+            if best.as_ref().map_or(true, |(best, _)| score > *best) {
+                let meter = Meter {
+                    beats_per_bar,
+                    first_downbeat: phase,
+                };
+                best = Some((score, meter));
+            }
+        }
+    }
+    best.map(|(_, meter)| meter)
+}
+
+/// One-pole low-pass filter at `BASS_CUTOFF`.
+fn low_pass(samples: &[f32], sr: f32) -> Vec<f32> {
+    let rc = 1.0 / (2.0 * PI * BASS_CUTOFF);
+    let alpha = (1.0 / sr) / (rc + 1.0 / sr);
+    let mut filtered = 0.0;
+    samples
+        .iter()
+        .map(|sample| {
+            filtered += alpha * (sample - filtered);
+            filtered
+        })
+        .collect()
+}
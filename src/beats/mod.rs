@@ -0,0 +1,294 @@
+mod cache;
+mod decode;
+mod error;
+mod features;
+mod file;
+mod grid;
+#[cfg(feature = "madmom")]
+mod madmom;
+mod meter;
+mod native;
+mod stream;
+mod tempo;
+
+#[cfg(feature = "madmom")]
+pub use self::madmom::MadmomDetector;
+pub use self::{
+    cache::{cached_summary, load_or_find_beats},
+    decode::{load_music, track_duration},
+    error::BeatError,
+    features::{find_features, FeatureFrame, FeaturesJob, MusicFeatures},
+    file::FileDetector,
+    grid::GridDetector,
+    meter::find_meter,
+    native::NativeDetector,
+    stream::{BeatStream, StreamDetector, StreamEvent},
+    tempo::{estimate_tempo, Tempo},
+};
+
+use rodio::Sink;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::f32::{consts::PI, INFINITY};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Default)]
+pub struct Music {
+    pub numbers: Vec<f32>,
+    pub sr: usize,
+}
+
+#[derive(Default)]
+pub struct Beats {
+    pub music: Music,
+    pub timestamps: Vec<f32>,
+    pub clicks: Vec<f32>,
+    pub intervals: Vec<f32>,
+    /// The beats that start a bar, empty when the meter is unknown.
+    pub downbeats: Vec<f32>,
+    /// 0 when the meter is unknown.
+    pub beats_per_bar: usize,
+    /// Length of the track, in seconds.
+    pub duration: f32,
+}
+
+/// A beat tracking algorithm. Detectors only see decoded audio, so they can
+/// be swapped without touching the code that loads or plays the tracks.
+pub trait BeatDetector {
+    /// Short name of the detector, used in logs and in the beat cache.
+    fn name(&self) -> &'static str;
+
+    /// Bumped whenever the algorithm changes, to invalidate cached beats.
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// Everything that tunes the detector. Cached beats found with other
+    /// parameters are analysed again.
+    fn parameters(&self) -> String {
+        String::new()
+    }
+
+    /// Finds the beats of mono samples played at `music.sr` Hz, as
+    /// timestamps in seconds from the start of the track.
+    fn detect(&self, music: &Music) -> Result<Vec<f32>, BeatError>;
+}
+
+/// Which `BeatDetector` analyses the tracks.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum DetectorKind {
+    /// The Rust beat tracker, needs nothing else installed.
+    Native,
+    /// madmom's RNN beat tracker, only available with the `madmom` feature.
+    Madmom,
+    /// Beat timestamps read from a text file, one per line.
+    File(String),
+    /// An even grid at the estimated tempo, without tracking the beats.
+    Grid,
+    /// The streaming tracker, which the game runs while the track plays
+    /// instead of analysing it first.
+    Stream,
+}
+
+impl Default for DetectorKind {
+    fn default() -> Self {
+        DetectorKind::Native
+    }
+}
+
+impl DetectorKind {
+    pub fn build(&self) -> Result<Box<dyn BeatDetector>, BeatError> {
+        match self {
+            DetectorKind::Native => Ok(Box::new(NativeDetector::default())),
+            #[cfg(feature = "madmom")]
+            DetectorKind::Madmom => Ok(Box::new(MadmomDetector)),
+            #[cfg(not(feature = "madmom"))]
+            DetectorKind::Madmom => Err(BeatError::DetectorUnavailable(
+                "the madmom detector needs the `madmom` feature".to_owned(),
+            )),
+            DetectorKind::File(path) => Ok(Box::new(FileDetector::new(path))),
+            DetectorKind::Grid => Ok(Box::new(GridDetector::default())),
+            DetectorKind::Stream => Ok(Box::new(StreamDetector::default())),
+        }
+    }
+}
+
+/// Beat detection settings, read from `config/beats.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BeatsConfig {
+    pub detector: DetectorKind,
+    /// Rate the tracks are resampled to before the analysis, as
+    /// `librosa.load` does to 22050 Hz. `None` analyses them at their own
+    /// rate, which the native detector is tuned for. The tracks the game
+    /// streams always keep their rate.
+    pub sample_rate: Option<usize>,
+    /// Tempo the game plays at when the track can't even be decoded to
+    /// estimate its tempo.
+    pub fallback_bpm: f32,
+}
+
+impl Default for BeatsConfig {
+    fn default() -> Self {
+        BeatsConfig {
+            detector: DetectorKind::default(),
+            sample_rate: None,
+            fallback_bpm: 120.0,
+        }
+    }
+}
+
+/// Decodes a track at `sample_rate`, or its own rate without one, and finds
+/// its beats with the given detector, and which of them start a bar. Fewer
+/// than two beats give no tempo to play at, so they are an error.
+pub fn find_beats(
+    filename: &str,
+    detector: &dyn BeatDetector,
+    sample_rate: Option<usize>,
+) -> Result<Beats, BeatError> {
+    let music = load_music(filename, sample_rate)?;
+    let timestamps = detector.detect(&music)?;
+    if timestamps.len() < 2 {
+        return Err(BeatError::NoBeatsFound {
+            detector: detector.name(),
+        });
+    }
+    let clicks = clicks(&timestamps, music.sr, music.numbers.len());
+    let mut intervals = beats_to_intervals(&timestamps);
+    intervals.reverse();
+    let (downbeats, beats_per_bar) = match find_meter(&music, &timestamps) {
+        Some(meter) => (meter.downbeats(&timestamps), meter.beats_per_bar),
+        None => (vec![], 0),
+    };
+    let duration = music.numbers.len() as f32 / music.sr as f32;
+    Ok(Beats {
+        music,
+        timestamps,
+        clicks,
+        intervals,
+        downbeats,
+        beats_per_bar,
+        duration,
+    })
+}
+
+/// Renders a click track with one short, decaying 1kHz tone at each of the
+/// given timestamps, the same way `librosa.clicks` does.
+pub fn clicks(timestamps: &[f32], sr: usize, len: usize) -> Vec<f32> {
+    let click_len = sr / 10;
+    let click = (0..click_len)
+        .map(|i| {
+            let t = i as f32 / sr as f32;
+            let decay = 2f32.powf(-10.0 * i as f32 / click_len as f32);
+            (2.0 * PI * 1000.0 * t).sin() * decay
+        })
+        .collect::<Vec<_>>();
+    let mut track = vec![0.0; len];
+    for timestamp in timestamps {
+        let start = (timestamp * sr as f32) as usize;
+        for (sample, value) in track.iter_mut().skip(start).zip(&click) {
+            *sample += value;
+        }
+    }
+    track
+}
+
+/// The time between each beat and the next, empty with fewer than two beats.
+pub fn beats_to_intervals(beats: &[f32]) -> Vec<f32> {
+    beats.windows(2).map(|pair| pair[1] - pair[0]).collect()
+}
+
+/// The tempo of the median interval, which shrugs off the odd missed beat,
+/// or 0 without intervals. Intervals that aren't numbers are left out.
+pub fn median_bpm(intervals: &[f32]) -> f32 {
+    let mut intervals = intervals
+        .iter()
+        .cloned()
+        .filter(|interval| interval.is_finite())
+        .collect::<Vec<_>>();
+    intervals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    match intervals.get(intervals.len() / 2) {
+        Some(interval) if *interval > 0.0 => 60.0 / interval,
+        _ => 0.0,
+    }
+}
+
+/// How steady the beats of a track are.
+#[derive(Clone, Debug)]
+pub struct TempoStats {
+    pub beats: usize,
+    pub median_bpm: f32,
+    pub mean_bpm: f32,
+    /// Tempo of the longest and of the shortest interval.
+    pub slowest_bpm: f32,
+    pub fastest_bpm: f32,
+    /// Standard deviation of the intervals, in milliseconds.
+    pub jitter_ms: f32,
+}
+
+/// Statistics of the intervals between beats, `None` without intervals.
+pub fn tempo_stats(intervals: &[f32]) -> Option<TempoStats> {
+    let intervals = intervals
+        .iter()
+        .cloned()
+        .filter(|interval| *interval > 0.0)
+        .collect::<Vec<_>>();
+    if intervals.is_empty() {
+        return None;
+    }
+    let count = intervals.len() as f32;
+    let mean = intervals.iter().sum::<f32>() / count;
+    let variance = intervals.iter().map(|i| (i - mean).powi(2)).sum::<f32>() / count;
+    let longest = intervals.iter().cloned().fold(0.0, f32::max);
+    let shortest = intervals.iter().cloned().fold(INFINITY, f32::min);
+    Some(TempoStats {
+        beats: intervals.len() + 1,
+        median_bpm: median_bpm(&intervals),
+        mean_bpm: 60.0 / mean,
+        slowest_bpm: 60.0 / longest,
+        fastest_bpm: 60.0 / shortest,
+        jitter_ms: variance.sqrt() * 1000.0,
+    })
+}
+
+/// Plays `click` after each of the intervals, in milliseconds, on the
+/// default output device. The clicks stop when the returned sink is dropped.
+pub fn play_beats(intervals: Vec<u64>, click: &Path) -> Result<Sink, BeatError> {
+    let device = rodio::default_output_device().ok_or(BeatError::NoOutputDevice)?;
+    let click_name = click.to_string_lossy();
+    let beat_file = File::open(click).map_err(|e| BeatError::io(&click_name, e))?;
+    let source = rodio::Decoder::new(BufReader::new(beat_file))
+        .map_err(|e| BeatError::Decode {
+            file: click_name.into_owned(),
+            reason: format!("{:?}", e),
+        })?
+        .buffered();
+    let sink = Sink::new(&device);
+    let it = intervals
+        .into_iter()
+        .map(move |interval| source.clone().delay(Duration::from_millis(interval)));
+    sink.append(rodio::source::from_iter(it));
+    Ok(sink)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn beats_to_intervals_needs_two_beats() {
+        assert!(beats_to_intervals(&[]).is_empty());
+        assert!(beats_to_intervals(&[1.0]).is_empty());
+        assert_eq!(beats_to_intervals(&[1.0, 1.5, 2.5]), vec![0.5, 1.0]);
+    }
+
+    #[test]
+    fn median_bpm_leaves_out_what_isnt_a_number() {
+        assert_eq!(median_bpm(&[]), 0.0);
+        assert_eq!(median_bpm(&[std::f32::NAN, 0.5, INFINITY, 0.5, 1.0]), 120.0);
+    }
+}
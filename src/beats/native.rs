@@ -0,0 +1,315 @@
+use super::{BeatDetector, BeatError, Music};
+use rustfft::{num_complex::Complex, FFTplanner, FFT};
+use std::{cmp::Ordering, f32::consts::PI, sync::Arc};
+
+/// Length of one analysis window, in samples.
+pub(super) const FRAME_SIZE: usize = 2048;
+/// Distance between two consecutive analysis windows, in samples.
+pub(super) const HOP_SIZE: usize = 512;
+const MIN_BPM: f32 = 40.0;
+const MAX_BPM: f32 = 240.0;
+
+/// Finds the beats without leaving Rust: the samples are turned into an
+/// onset strength envelope, its tempo is estimated and the beats are picked
+/// with dynamic programming.
+#[derive(Clone, Debug)]
+pub struct NativeDetector {
+    /// Tempo the estimator leans towards when the envelope is ambiguous.
+    pub start_bpm: f32,
+    /// How strictly the tracker sticks to the estimated tempo.
+    pub tightness: f32,
+}
+
+impl Default for NativeDetector {
+    fn default() -> Self {
+        NativeDetector {
+            start_bpm: 120.0,
+            tightness: 100.0,
+        }
+    }
+}
+
+impl BeatDetector for NativeDetector {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn parameters(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn detect(&self, music: &Music) -> Result<Vec<f32>, BeatError> {
+        let envelope = onset_strength(&music.numbers);
+        let fps = music.sr as f32 / HOP_SIZE as f32;
+        let period = estimate_period(&envelope, fps, self.start_bpm);
+        Ok(track_beats(&envelope, period, self.tightness)
+            .into_iter()
+            .map(|frame| frame as f32 / fps)
+            .collect())
+    }
+}
+
+/// Windowed FFT of one frame at a time, shared by the spectrogram of a
+/// whole track and the streaming tracker.
+pub(super) struct Spectrum {
+    window: Vec<f32>,
+    fft: Arc<dyn FFT<f32>>,
+    input: Vec<Complex<f32>>,
+    output: Vec<Complex<f32>>,
+}
+
+impl Spectrum {
+    pub(super) fn new() -> Spectrum {
+        let window = (0..FRAME_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME_SIZE as f32).cos())
+            .collect();
+        let mut planner = FFTplanner::new(false);
+        let input = vec![Complex::new(0.0, 0.0); FRAME_SIZE];
+        Spectrum {
+            window,
+            fft: planner.plan_fft(FRAME_SIZE),
+            output: input.clone(),
+            input,
+        }
+    }
+
+    /// Magnitudes of the `FRAME_SIZE / 2 + 1` bins of the frame of `samples`
+    /// that begins at `start`. Samples outside of them count as silence.
+    pub(super) fn magnitudes(&mut self, samples: &[f32], start: isize) -> Vec<f32> {
+        for (i, value) in self.input.iter_mut().enumerate() {
+            let index = start + i as isize;
+            let sample = if index >= 0 && (index as usize) < samples.len() {
+                samples[index as usize]
+            } else {
+                0.0
+            };
+            *value = Complex::new(sample * self.window[i], 0.0);
+        }
+        self.fft.process(&mut self.input, &mut self.output);
+        self.output[..=FRAME_SIZE / 2]
+            .iter()
+            .map(|bin| bin.norm())
+            .collect()
+    }
+}
+
+/// Magnitude spectrogram, one frame of `FRAME_SIZE / 2 + 1` bins every
+/// `HOP_SIZE` samples. Frames are centred, so frame `n` describes the track
+/// at `n * HOP_SIZE / sr` seconds.
+pub(super) fn spectrogram(samples: &[f32]) -> Vec<Vec<f32>> {
+    let mut spectrum = Spectrum::new();
+    let frames = samples.len() / HOP_SIZE + 1;
+    (0..frames)
+        .map(|frame| {
+            let start = (frame * HOP_SIZE) as isize - (FRAME_SIZE / 2) as isize;
+            spectrum.magnitudes(samples, start)
+        })
+        .collect()
+}
+
+/// Spectral flux of the track: how much energy shows up from one frame to
+/// the next, averaged over the frequency bins.
+pub(super) fn onset_strength(samples: &[f32]) -> Vec<f32> {
+    spectral_flux(&spectrogram(samples))
+}
+
+/// The onset strength of every frame of a magnitude spectrogram.
+pub(super) fn spectral_flux(spectrogram: &[Vec<f32>]) -> Vec<f32> {
+    let compressed = spectrogram
+        .iter()
+        .map(|frame| compress(frame))
+        .collect::<Vec<_>>();
+    let mut envelope = vec![0.0];
+    envelope.extend(compressed.windows(2).map(|pair| flux(&pair[0], &pair[1])));
+    envelope
+}
+
+/// Compresses magnitudes logarithmically, so that quiet onsets count too.
+pub(super) fn compress(magnitudes: &[f32]) -> Vec<f32> {
+    magnitudes
+        .iter()
+        .map(|magnitude| (1.0 + 1000.0 * magnitude).ln())
+        .collect()
+}
+
+/// How much energy shows up from the `previous` compressed frame to the
+/// `current` one, averaged over the frequency bins.
+pub(super) fn flux(previous: &[f32], current: &[f32]) -> f32 {
+    current
+        .iter()
+        .zip(previous)
+        .map(|(now, before)| (now - before).max(0.0))
+        .sum::<f32>()
+        / current.len() as f32
+}
+
+/// Estimates the beat period, in frames, from the autocorrelation of the
+/// onset envelope. Lags are weighted by a log-normal prior around
+/// `start_bpm`, so that half and double tempos lose against the usual one.
+pub(super) fn estimate_period(envelope: &[f32], fps: f32, start_bpm: f32) -> f32 {
+    let min_lag = (60.0 * fps / MAX_BPM).floor().max(1.0) as usize;
+    let max_lag = ((60.0 * fps / MIN_BPM).ceil() as usize).min(envelope.len().saturating_sub(1));
+    if min_lag + 2 > max_lag {
+        return 60.0 * fps / start_bpm;
+    }
+    let scores = (min_lag..=max_lag)
+        .map(|lag| {
+            let correlation = envelope
+                .iter()
+                .zip(&envelope[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / (envelope.len() - lag) as f32;
+            let bpm = 60.0 * fps / lag as f32;
+            let prior = (-0.5 * (bpm / start_bpm).log2().powi(2)).exp();
+            correlation * prior
+        })
+        .collect::<Vec<_>>();
+    let best = (0..scores.len())
+        .max_by(|&a, &b| scores[a].partial_cmp(&scores[b]).unwrap_or(Ordering::Equal))
+        .unwrap_or(0);
+    // Refine the integer lag with a parabola through its neighbours.
+    let offset = if best > 0 && best + 1 < scores.len() {
+        let (left, centre, right) = (scores[best - 1], scores[best], scores[best + 1]);
+        let curvature = left - 2.0 * centre + right;
+        if curvature < 0.0 {
+            0.5 * (left - right) / curvature
+        } else {
+            0.0
+        }
+    } else {
+        0.0
+    };
+    (min_lag + best) as f32 + offset
+}
+
+/// Picks the beats with the dynamic programming tracker from Ellis (2007):
+/// every frame scores its own onset strength plus the best score of a
+/// previous beat roughly one period earlier, penalised by how far that gap
+/// strays from the period. The best chain is then read backwards.
+fn track_beats(envelope: &[f32], period: f32, tightness: f32) -> Vec<usize> {
+    let deviation = std_dev(envelope);
+    if envelope.len() < 3 || deviation <= 0.0 {
+        return vec![];
+    }
+    let normalised = envelope
+        .iter()
+        .map(|value| value / deviation)
+        .collect::<Vec<_>>();
+    let local = local_score(&normalised, period);
+
+    let window_start = (-2.0 * period).round() as isize;
+    let window_end = (-period / 2.0).round() as isize;
+    let transitions = (window_start..=window_end)
+        .map(|offset| {
+            let weight = -tightness * (-offset as f32 / period).ln().powi(2);
+            (offset, weight)
+        })
+        .collect::<Vec<_>>();
+
+    let threshold = 0.01 * local.iter().cloned().fold(0.0, f32::max);
+    let mut cumulative = vec![0.0; local.len()];
+    let mut backlink = vec![None; local.len()];
+    let mut first_beat = true;
+    for i in 0..local.len() {
+        let (previous, score) = transitions
+            .iter()
+            .map(|&(offset, weight)| {
+                let j = i as isize + offset;
+                if j >= 0 {
+                    (Some(j as usize), cumulative[j as usize] + weight)
+                } else {
+                    (None, weight)
+                }
+            })
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+            .unwrap_or((None, 0.0));
+        cumulative[i] = local[i] + score;
+        if first_beat && local[i] < threshold {
+            backlink[i] = None;
+        } else {
+            backlink[i] = previous;
+            first_beat = false;
+        }
+    }
+
+    // The last beat is the latest local maximum of the cumulative score that
+    // is not much weaker than the typical one.
+    let maxima = (1..cumulative.len() - 1)
+        .filter(|&i| cumulative[i] > cumulative[i - 1] && cumulative[i] >= cumulative[i + 1])
+        .collect::<Vec<_>>();
+    let mut peaks = maxima.iter().map(|&i| cumulative[i]).collect::<Vec<_>>();
+    peaks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let median = match peaks.get(peaks.len() / 2) {
+        Some(median) => *median,
+        None => return vec![],
+    };
+    let last = match maxima
+        .iter()
+        .rev()
+        .find(|&&i| cumulative[i] >= 0.5 * median)
+    {
+        Some(last) => *last,
+        None => return vec![],
+    };
+
+    let mut beats = vec![last];
+    while let Some(previous) = backlink[*beats.last().unwrap()] {
+        beats.push(previous);
+    }
+    beats.reverse();
+    trim_beats(&local, beats)
+}
+
+/// Smooths the envelope with a gaussian a fraction of a period wide, so that
+/// onsets slightly off the grid still count.
+pub(super) fn local_score(envelope: &[f32], period: f32) -> Vec<f32> {
+    let radius = period.round() as isize;
+    let kernel = (-radius..=radius)
+        .map(|k| (-0.5 * (k as f32 * 32.0 / period).powi(2)).exp())
+        .collect::<Vec<_>>();
+    (0..envelope.len() as isize)
+        .map(|i| {
+            kernel
+                .iter()
+                .enumerate()
+                .filter_map(|(k, weight)| {
+                    let j = i + k as isize - radius;
+                    if j >= 0 && (j as usize) < envelope.len() {
+                        Some(envelope[j as usize] * weight)
+                    } else {
+                        None
+                    }
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// Drops the weak beats the tracker extrapolates into silence at the start
+/// and at the end of the track.
+fn trim_beats(local: &[f32], mut beats: Vec<usize>) -> Vec<usize> {
+    if beats.is_empty() {
+        return beats;
+    }
+    let energy = beats.iter().map(|&b| local[b].powi(2)).sum::<f32>() / beats.len() as f32;
+    let threshold = 0.5 * energy.sqrt();
+    while beats.last().map_or(false, |&b| local[b] < threshold) {
+        beats.pop();
+    }
+    let first = beats
+        .iter()
+        .position(|&b| local[b] >= threshold)
+        .unwrap_or_else(|| beats.len());
+    beats.split_off(first)
+}
+
+fn std_dev(values: &[f32]) -> f32 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    let variance =
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / (values.len() - 1) as f32;
+    variance.sqrt()
+}
@@ -0,0 +1,397 @@
+use super::{
+    decode::{downmix, open_music},
+    features::{extract_features, FeaturesJob},
+    native::{compress, estimate_period, flux, local_score, Spectrum, FRAME_SIZE, HOP_SIZE},
+    BeatDetector, BeatError, Music, MusicFeatures,
+};
+use std::{
+    cmp::Ordering,
+    collections::VecDeque,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Seconds of onsets the tempo and the phase are estimated on. A longer
+/// history is steadier, a shorter one follows tempo changes sooner.
+const HISTORY_SECONDS: f32 = 8.0;
+/// Seconds of the track heard before the first estimate.
+const WARMUP_SECONDS: f32 = 3.0;
+/// Seconds between two estimates.
+const UPDATE_SECONDS: f32 = 1.0;
+/// Samples handed to the tracker at a time, about what an audio callback
+/// gets.
+const CHUNK_SIZE: usize = 1024;
+
+/// Finds the beats of a track as it plays, from the samples heard so far.
+/// The onset envelope is computed frame by frame like the one of the
+/// `NativeDetector`, and every `UPDATE_SECONDS` the tempo and the phase are
+/// estimated again on the last `HISTORY_SECONDS` of it. Beats are then
+/// emitted as soon as the analysis passes them, at most `latency` seconds
+/// after they are heard. The beats of the first `WARMUP_SECONDS` all come
+/// at once, with the first estimate.
+pub struct StreamingTracker {
+    sr: usize,
+    start_bpm: f32,
+    spectrum: Spectrum,
+    /// Samples not analysed yet, from the start of the next frame.
+    pending: Vec<f32>,
+    /// Compressed spectrum of the last frame.
+    previous: Option<Vec<f32>>,
+    /// The last `HISTORY_SECONDS` of the onset envelope.
+    envelope: VecDeque<f32>,
+    /// Frames analysed so far.
+    frames: usize,
+    /// Frame of the last estimate.
+    estimated: Option<usize>,
+    /// Beat period and frame of the next beat, once estimated.
+    next_beat: Option<(f32, f32)>,
+}
+
+impl StreamingTracker {
+    /// A tracker for mono samples at `sr` Hz, leaning towards `start_bpm`
+    /// when the envelope is ambiguous.
+    pub fn new(sr: usize, start_bpm: f32) -> StreamingTracker {
+        StreamingTracker {
+            sr,
+            start_bpm,
+            spectrum: Spectrum::new(),
+            // Frames are centred, as they are over a whole track.
+            pending: vec![0.0; FRAME_SIZE / 2],
+            previous: None,
+            envelope: VecDeque::new(),
+            frames: 0,
+            estimated: None,
+            next_beat: None,
+        }
+    }
+
+    /// How long after a beat is heard it is emitted, at most, once the
+    /// tempo is known.
+    pub fn latency(&self) -> f32 {
+        (FRAME_SIZE / 2 + HOP_SIZE) as f32 / self.sr as f32
+    }
+
+    /// Analyses the next `samples` of the track, and returns the beats they
+    /// complete, in seconds from the start of the track.
+    pub fn push(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.pending.extend_from_slice(samples);
+        let mut beats = vec![];
+        let mut start = 0;
+        while self.pending.len() - start >= FRAME_SIZE {
+            let magnitudes = self
+                .spectrum
+                .magnitudes(&self.pending[start..start + FRAME_SIZE], 0);
+            start += HOP_SIZE;
+            self.analyse(compress(&magnitudes), &mut beats);
+        }
+        self.pending.drain(..start);
+        beats
+    }
+
+    /// Analyses the end of a track that stopped, and returns its last beats.
+    pub fn finish(&mut self) -> Vec<f32> {
+        self.push(&[0.0; FRAME_SIZE / 2])
+    }
+
+    fn fps(&self) -> f32 {
+        self.sr as f32 / HOP_SIZE as f32
+    }
+
+    fn analyse(&mut self, frame: Vec<f32>, beats: &mut Vec<f32>) {
+        let onset = self
+            .previous
+            .as_ref()
+            .map_or(0.0, |previous| flux(previous, &frame));
+        self.previous = Some(frame);
+        self.envelope.push_back(onset);
+        if self.envelope.len() as f32 > HISTORY_SECONDS * self.fps() {
+            self.envelope.pop_front();
+        }
+        self.frames += 1;
+
+        let due = match self.estimated {
+            Some(estimated) => (self.frames - estimated) as f32 >= UPDATE_SECONDS * self.fps(),
+            None => self.frames as f32 >= WARMUP_SECONDS * self.fps(),
+        };
+        if due {
+            self.estimate();
+        }
+        let (now, fps) = ((self.frames - 1) as f32, self.fps());
+        if let Some((period, next)) = self.next_beat.as_mut() {
+            while *next <= now {
+                beats.push(*next / fps);
+                *next += *period;
+            }
+        }
+    }
+
+    /// Estimates the tempo and the phase again, and moves the next beat onto
+    /// them. Beats already emitted stay, so the next one is never less than
+    /// half a period after the last one.
+    fn estimate(&mut self) {
+        self.estimated = Some(self.frames);
+        let envelope = self.envelope.iter().cloned().collect::<Vec<_>>();
+        let period = estimate_period(&envelope, self.fps(), self.start_bpm);
+        if period < 1.0 || period.is_nan() {
+            return;
+        }
+        let local = local_score(&envelope, period);
+        // How far back from the latest frame the comb of beats that lands on
+        // the most onset strength starts.
+        let strength = |offset: usize| {
+            let beats = (0..)
+                .map(|beat| offset as f32 + beat as f32 * period)
+                .take_while(|back| (back.round() as usize) < local.len())
+                .map(|back| local[local.len() - 1 - back.round() as usize]);
+            let (sum, count) = beats.fold((0.0, 0), |(sum, count), strength| {
+                (sum + strength, count + 1)
+            });
+            if count == 0 {
+                0.0
+            } else {
+                sum / count as f32
+            }
+        };
+        let offset = (0..period.ceil() as usize)
+            .max_by(|&a, &b| {
+                strength(a)
+                    .partial_cmp(&strength(b))
+                    .unwrap_or(Ordering::Equal)
+            })
+            .unwrap_or(0);
+        let last_beat = (self.frames - 1).saturating_sub(offset) as f32;
+
+        let mut next = last_beat;
+        match self.next_beat {
+            Some((previous_period, previous_next)) => {
+                let emitted = previous_next - previous_period;
+                while next < emitted + period / 2.0 {
+                    next += period;
+                }
+            }
+            None => {
+                let first_frame = (self.frames - envelope.len()) as f32;
+                while next - period >= first_frame {
+                    next -= period;
+                }
+            }
+        }
+        self.next_beat = Some((period, next));
+    }
+}
+
+/// The `StreamingTracker` over a whole track, handed to it a chunk at a
+/// time as if it were playing. Mostly useful to compare the beats the game
+/// streams with the ones of the other detectors.
+#[derive(Clone, Debug)]
+pub struct StreamDetector {
+    /// Handed to the `StreamingTracker`, which leans towards it every time
+    /// it estimates the tempo again on the last `HISTORY_SECONDS` heard, so
+    /// that a passage of the track that is ambiguous doesn't flip the beats
+    /// to half or double the tempo.
+    pub start_bpm: f32,
+}
+
+impl Default for StreamDetector {
+    fn default() -> Self {
+        StreamDetector { start_bpm: 120.0 }
+    }
+}
+
+impl StreamDetector {
+    /// Starts finding the beats of the track in `filename` in the
+    /// background, as fast as it decodes or, when `realtime`, no faster than
+    /// it plays.
+    pub fn stream(&self, filename: &str, realtime: bool) -> Result<BeatStream, BeatError> {
+        self.spawn(filename, realtime, None)
+    }
+
+    /// Starts finding the beats of the track in `filename` in the
+    /// background like `stream`, and extracts its features from the same
+    /// samples once all of it is decoded.
+    pub fn stream_with_features(
+        &self,
+        filename: &str,
+        realtime: bool,
+    ) -> Result<(BeatStream, FeaturesJob), BeatError> {
+        let (sender, receiver) = mpsc::channel();
+        let stream = self.spawn(filename, realtime, Some(sender))?;
+        Ok((stream, FeaturesJob::from_receiver(receiver)))
+    }
+
+    fn spawn(
+        &self,
+        filename: &str,
+        realtime: bool,
+        features: Option<Sender<Result<MusicFeatures, BeatError>>>,
+    ) -> Result<BeatStream, BeatError> {
+        let (mut decoder, channels, sr) = open_music(filename)?;
+        let mut tracker = StreamingTracker::new(sr, self.start_bpm);
+        // The beats of a chunk are only found once all of it is decoded.
+        let latency = tracker.latency() + CHUNK_SIZE as f32 / sr as f32;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let started = Instant::now();
+            let mut heard = 0;
+            // The whole track, when its features are extracted at the end.
+            let mut track = vec![];
+            loop {
+                let samples = decoder
+                    .by_ref()
+                    .take(CHUNK_SIZE * channels)
+                    .collect::<Vec<i16>>();
+                let ended = samples.len() < CHUNK_SIZE * channels;
+                let mono = downmix(&samples, channels);
+                heard += mono.len();
+                if features.is_some() {
+                    track.extend_from_slice(&mono);
+                }
+                if realtime {
+                    let due = Duration::from_millis((1000 * heard / sr) as u64);
+                    if let Some(wait) = due.checked_sub(started.elapsed()) {
+                        thread::sleep(wait);
+                    }
+                }
+                let mut beats = tracker.push(&mono);
+                if ended {
+                    beats.extend(tracker.finish());
+                }
+                for beat in beats {
+                    // Nobody listens anymore.
+                    if sender.send(StreamEvent::Beat(beat)).is_err() {
+                        return;
+                    }
+                }
+                if ended {
+                    let _ = sender.send(StreamEvent::End(heard as f32 / sr as f32));
+                    if let Some(features) = features {
+                        let music = Music { numbers: track, sr };
+                        let _ = features.send(Ok(extract_features(&music)));
+                    }
+                    return;
+                }
+            }
+        });
+        Ok(BeatStream {
+            receiver: Mutex::new(receiver),
+            latency,
+        })
+    }
+}
+
+impl BeatDetector for StreamDetector {
+    fn name(&self) -> &'static str {
+        "stream"
+    }
+
+    fn parameters(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn detect(&self, music: &Music) -> Result<Vec<f32>, BeatError> {
+        let mut tracker = StreamingTracker::new(music.sr, self.start_bpm);
+        let mut beats = music
+            .numbers
+            .chunks(CHUNK_SIZE)
+            .flat_map(|chunk| tracker.push(chunk))
+            .collect::<Vec<_>>();
+        beats.extend(tracker.finish());
+        Ok(beats)
+    }
+}
+
+/// What a `BeatStream` found since it was last polled.
+#[derive(Clone, Copy, Debug)]
+pub enum StreamEvent {
+    /// A beat, in seconds from the start of the track.
+    Beat(f32),
+    /// The track ended, this many seconds long. Nothing comes after.
+    End(f32),
+}
+
+/// The beats of a track found in the background by a `StreamDetector`. The
+/// analysis stops when the stream is dropped.
+pub struct BeatStream {
+    receiver: Mutex<Receiver<StreamEvent>>,
+    latency: f32,
+}
+
+impl BeatStream {
+    /// How late the beats come at most, in seconds, once the tempo is
+    /// known, on top of how late they are polled.
+    pub fn latency(&self) -> f32 {
+        self.latency
+    }
+
+    /// Everything found since the last poll, in order, without waiting.
+    pub fn poll(&self) -> Vec<StreamEvent> {
+        match self.receiver.lock() {
+            Ok(receiver) => receiver.try_iter().collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::beats_to_intervals;
+
+    const SR: usize = 22_050;
+
+    /// `seconds` of short bursts of noise every `period` seconds.
+    fn click_train(period: f32, seconds: f32) -> Vec<f32> {
+        let mut seed = 1u32;
+        (0..(seconds * SR as f32) as usize)
+            .map(|i| {
+                let since_click = (i as f32 / SR as f32) % period;
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                if since_click < 0.01 {
+                    f32::from((seed >> 16) as u16) / 32_768.0 - 1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn streaming_tracker_follows_a_click_train() {
+        let period = 0.5;
+        let mut tracker = StreamingTracker::new(SR, 120.0);
+        let mut beats = click_train(period, 12.0)
+            .chunks(CHUNK_SIZE)
+            .flat_map(|chunk| tracker.push(chunk))
+            .collect::<Vec<_>>();
+        beats.extend(tracker.finish());
+
+        assert!(beats.len() >= 20, "{:?}", beats);
+        assert!(beats.windows(2).all(|pair| pair[1] > pair[0]));
+        // The onsets are only known to a frame or two.
+        let tolerance = 2.0 * HOP_SIZE as f32 / SR as f32;
+        for beat in &beats {
+            let off = beat % period;
+            let error = off.min(period - off);
+            assert!(error < tolerance, "{} is {} s off the clicks", beat, error);
+        }
+        let mut intervals = beats_to_intervals(&beats);
+        intervals.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let median = intervals[intervals.len() / 2];
+        assert!((median - period).abs() < 0.01, "{}", median);
+    }
+
+    #[test]
+    fn streaming_tracker_waits_for_the_warmup() {
+        let mut tracker = StreamingTracker::new(SR, 120.0);
+        let warmup = (WARMUP_SECONDS * SR as f32) as usize;
+        let beats = click_train(0.5, 12.0)[..warmup / 2]
+            .chunks(CHUNK_SIZE)
+            .flat_map(|chunk| tracker.push(chunk))
+            .collect::<Vec<_>>();
+        assert!(beats.is_empty());
+    }
+}
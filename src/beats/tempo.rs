@@ -0,0 +1,124 @@
+use super::{
+    native::{estimate_period, local_score, onset_strength, HOP_SIZE},
+    Music,
+};
+use std::cmp::Ordering;
+
+/// How far around the autocorrelation estimate the period is refined, and
+/// in how many steps on each side.
+const PERIOD_SPREAD: f32 = 0.02;
+const PERIOD_STEPS: i32 = 40;
+
+/// The steady tempo of a track, estimated from the autocorrelation of its
+/// onset envelope.
+#[derive(Clone, Debug)]
+pub struct Tempo {
+    pub bpm: f32,
+    /// How regularly the onsets come back at that tempo, from 0 for no pulse
+    /// at all to 1 for a metronome.
+    pub confidence: f32,
+    /// Time of the first beat, in seconds, less than a beat from the start.
+    pub phase: f32,
+}
+
+impl Tempo {
+    /// Evenly spaced beats at the tempo, from the phase until `duration`
+    /// seconds. Empty without a tempo.
+    pub fn grid(&self, duration: f32) -> Vec<f32> {
+        if self.bpm <= 0.0 || self.bpm.is_nan() {
+            return vec![];
+        }
+        let period = 60.0 / self.bpm;
+        (0..)
+            .map(|beat| self.phase + beat as f32 * period)
+            .take_while(|time| *time < duration)
+            .collect()
+    }
+}
+
+/// Estimates the tempo of mono samples, leaning towards `start_bpm` when
+/// the envelope is ambiguous. `None` for silence or a track too short to
+/// hold a beat.
+pub fn estimate_tempo(music: &Music, start_bpm: f32) -> Option<Tempo> {
+    let envelope = onset_strength(&music.numbers);
+    let fps = music.sr as f32 / HOP_SIZE as f32;
+    let period = estimate_period(&envelope, fps, start_bpm);
+    let lag = period.round() as usize;
+    if lag == 0 || lag >= envelope.len() {
+        return None;
+    }
+
+    let mean = envelope.iter().sum::<f32>() / envelope.len() as f32;
+    let centred = envelope.iter().map(|e| e - mean).collect::<Vec<_>>();
+    let energy = centred.iter().map(|c| c * c).sum::<f32>() / centred.len() as f32;
+    if energy <= 0.0 {
+        return None;
+    }
+    let correlation = centred
+        .iter()
+        .zip(&centred[lag..])
+        .map(|(a, b)| a * b)
+        .sum::<f32>()
+        / (centred.len() - lag) as f32;
+
+    // A period a hair off drifts a whole beat away over a long track, so
+    // it is refined along with the phase against every onset of the track.
+    let (period, phase) = fit_grid(&local_score(&envelope, period), period);
+    Some(Tempo {
+        bpm: 60.0 * fps / period,
+        confidence: (correlation / energy).max(0.0).min(1.0),
+        phase: phase / fps,
+    })
+}
+
+/// The period and the offset, in frames, of the comb of beats that lands on
+/// the most onset strength, with the period close to `estimate`.
+fn fit_grid(envelope: &[f32], estimate: f32) -> (f32, f32) {
+    let strength = |period: f32, offset: f32| {
+        let beats = (0..)
+            .map(|beat| (offset + beat as f32 * period).round() as usize)
+            .take_while(|&frame| frame < envelope.len());
+        let (sum, count) = beats.fold((0.0, 0), |(sum, count), frame| {
+            (sum + envelope[frame], count + 1)
+        });
+        if count == 0 {
+            0.0
+        } else {
+            sum / count as f32
+        }
+    };
+    (-PERIOD_STEPS..=PERIOD_STEPS)
+        .map(|step| estimate * (1.0 + PERIOD_SPREAD * step as f32 / PERIOD_STEPS as f32))
+        .flat_map(|period| (0..period.ceil() as usize).map(move |offset| (period, offset as f32)))
+        .max_by(|&(p1, o1), &(p2, o2)| {
+            strength(p1, o1)
+                .partial_cmp(&strength(p2, o2))
+                .unwrap_or(Ordering::Equal)
+        })
+        .unwrap_or((estimate, 0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tempo(bpm: f32) -> Tempo {
+        Tempo {
+            bpm,
+            confidence: 1.0,
+            phase: 0.25,
+        }
+    }
+
+    #[test]
+    fn grid_starts_at_the_phase() {
+        assert_eq!(tempo(120.0).grid(2.0), vec![0.25, 0.75, 1.25, 1.75]);
+    }
+
+    #[test]
+    fn grid_is_empty_without_a_tempo() {
+        assert!(tempo(0.0).grid(2.0).is_empty());
+        assert!(tempo(-120.0).grid(2.0).is_empty());
+        assert!(tempo(std::f32::NAN).grid(2.0).is_empty());
+    }
+}
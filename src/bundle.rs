@@ -1,12 +1,22 @@
-use crate::systems::{BounceSystem, MoveBallsSystem, MovePaddleSystem, PaddleSystem, WinnerSystem};
+use crate::{
+    audio::PlaybackClock,
+    physics::FixedTimestep,
+    systems::{
+        BounceSystem, MoveBallsSystem, MovePaddleSystem, PaddleSystem, RestorePoseSystem,
+        RhythmSystem, StorePoseSystem, WinnerSystem,
+    },
+};
 use amethyst::{
     core::bundle::SystemBundle,
-    ecs::prelude::{DispatcherBuilder, World},
+    ecs::prelude::{Dispatcher, DispatcherBuilder, World, WorldExt},
     error::Error,
 };
 
 /// A bundle is a convenient way to initialise related resources, components and systems in a
 /// world. This bundle prepares the world for a game of pong.
+///
+/// These are the physics systems: every dispatch is one `FixedTimestep` step, so they are not
+/// part of the game data but stepped by the `Pong` state, see `physics_dispatcher`.
 pub struct PongBundle;
 
 impl<'a, 'b> SystemBundle<'a, 'b> for PongBundle {
@@ -15,19 +25,56 @@ impl<'a, 'b> SystemBundle<'a, 'b> for PongBundle {
         _world: &mut World,
         builder: &mut DispatcherBuilder<'a, 'b>,
     ) -> Result<(), Error> {
-        builder.add(MoveBallsSystem, "ball_system", &[]);
-        builder.add(PaddleSystem, "paddle_system", &[]);
+        builder.add(RestorePoseSystem, "restore_pose", &[]);
+        builder.add(MoveBallsSystem, "ball_system", &["restore_pose"]);
+        builder.add(PaddleSystem, "paddle_system", &["restore_pose"]);
         builder.add(MovePaddleSystem, "move_paddle", &["paddle_system", "ball_system"]);
         builder.add(
             BounceSystem,
             "collision_system",
             &["paddle_system", "ball_system"],
         );
+        // Both change the balls, so they are ordered to keep the steps reproducible.
         builder.add(
             WinnerSystem,
             "winner_system",
-            &["paddle_system", "ball_system"],
+            &["paddle_system", "ball_system", "collision_system"],
+        );
+        builder.add(
+            RhythmSystem::default(),
+            "rhythm_system",
+            &["collision_system", "winner_system"],
+        );
+        builder.add(
+            StorePoseSystem,
+            "store_pose",
+            &["move_paddle", "collision_system", "winner_system"],
         );
         Ok(())
     }
 }
+
+/// Builds the dispatcher of the `PongBundle`, which runs one physics step per dispatch.
+pub fn physics_dispatcher<'a, 'b>(
+    world: &mut World,
+    mut builder: DispatcherBuilder<'a, 'b>,
+) -> Result<Dispatcher<'a, 'b>, Error> {
+    PongBundle.build(world, &mut builder)?;
+    let mut dispatcher = builder.build();
+    dispatcher.setup(world);
+    Ok(dispatcher)
+}
+
+/// Runs as many physics steps as a frame of `delta_seconds` makes up for,
+/// each at its own time of the music. The `PlaybackClock` is still where the
+/// last frame left it, and only moves on after the steps.
+pub fn run_physics_steps(world: &World, physics: &mut Dispatcher<'_, '_>, delta_seconds: f32) {
+    let position = world.read_resource::<PlaybackClock>().position;
+    let steps = world
+        .write_resource::<FixedTimestep>()
+        .start_frame(position, delta_seconds);
+    for _ in 0..steps {
+        physics.dispatch(world);
+        world.write_resource::<FixedTimestep>().next_step();
+    }
+}
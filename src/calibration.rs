@@ -0,0 +1,182 @@
+use crate::{
+    audio::{play_bounce, AudioOffset, Sounds},
+    config::GameConfig,
+};
+use amethyst::{
+    assets::{AssetStorage, Handle},
+    audio::{output::Output, AudioSink, Source},
+    core::{math::Vector3, timing::Time, transform::Transform},
+    ecs::prelude::{Entity, World},
+    input::InputEvent,
+    prelude::*,
+    renderer::{SpriteRender, SpriteSheet},
+    utils::application_root_dir,
+};
+use log::{error, info};
+use std::{cmp::Ordering, time::Instant};
+
+/// Tempo of the calibration clicks.
+const CALIBRATION_BPM: f32 = 100.0;
+/// Taps thrown away while the player finds the beat.
+const WARM_UP_TAPS: usize = 4;
+/// Taps the offset is measured from.
+const MEASURED_TAPS: usize = 16;
+/// How long the ball stays big after each click.
+const FLASH_SECONDS: f32 = 0.1;
+
+/// Measures the audio latency: `beat.wav` clicks at a steady tempo while the
+/// ball flashes, and the player taps along. The median gap between the taps
+/// and the clicks becomes the `AudioOffset`, which is saved to
+/// `config/calibration.ron`. The game and its music are paused meanwhile.
+pub struct Calibration {
+    sprite_sheet_handle: Handle<SpriteSheet>,
+    ball: Option<Entity>,
+    start: Instant,
+    /// When each click was handed to the audio output.
+    clicks: Vec<Instant>,
+    /// Seconds between each tap and its nearest click.
+    taps: Vec<f32>,
+}
+
+impl Calibration {
+    pub fn new(sprite_sheet_handle: Handle<SpriteSheet>) -> Calibration {
+        Calibration {
+            sprite_sheet_handle,
+            ball: None,
+            start: Instant::now(),
+            clicks: vec![],
+            taps: vec![],
+        }
+    }
+
+    fn period() -> f32 {
+        60.0 / CALIBRATION_BPM
+    }
+
+    /// Seconds from the click nearest to the tap to the tap itself; negative
+    /// when the player tapped early.
+    fn tap_offset(&self, tap: Instant) -> Option<f32> {
+        self.clicks
+            .iter()
+            .map(|&click| signed_seconds(tap, click))
+            .min_by(|a, b| a.abs().partial_cmp(&b.abs()).unwrap_or(Ordering::Equal))
+    }
+
+    fn offset(&self) -> AudioOffset {
+        let mut taps = self.taps[WARM_UP_TAPS..].to_vec();
+        taps.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        AudioOffset {
+            seconds: taps[taps.len() / 2],
+        }
+    }
+}
+
+impl SimpleState for Calibration {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let StateData { world, .. } = data;
+        if let Some(sink) = world.try_fetch::<AudioSink>() {
+            sink.pause();
+        }
+        world.write_resource::<Time>().set_time_scale(0.0);
+        self.ball
+            .replace(initialise_flash(world, self.sprite_sheet_handle.clone()));
+        self.start = Instant::now();
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let StateData { world, .. } = data;
+        if let Some(ball) = self.ball.take() {
+            if let Err(e) = world.delete_entity(ball) {
+                error!("Could not remove the calibration ball: {}", e);
+            }
+        }
+        world.write_resource::<Time>().set_time_scale(1.0);
+        if let Some(sink) = world.try_fetch::<AudioSink>() {
+            sink.play();
+        }
+    }
+
+    fn handle_event(
+        &mut self,
+        data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        if let StateEvent::Input(InputEvent::ActionPressed(action)) = event {
+            match action.as_str() {
+                "tap" => {
+                    if let Some(offset) = self.tap_offset(Instant::now()) {
+                        self.taps.push(offset);
+                    }
+                    if self.taps.len() >= WARM_UP_TAPS + MEASURED_TAPS {
+                        save_offset(data.world, self.offset());
+                        return Trans::Pop;
+                    }
+                }
+                "cancel" => return Trans::Pop,
+                _ => {}
+            }
+        }
+        Trans::None
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        let elapsed = self.start.elapsed().as_secs_f32();
+        let beat = (elapsed / Calibration::period()) as usize;
+        if beat >= self.clicks.len() {
+            self.clicks.push(Instant::now());
+            let sounds = data.world.read_resource::<Sounds>();
+            let storage = data.world.read_resource::<AssetStorage<Source>>();
+            let output = data.world.try_fetch::<Output>();
+            play_bounce(Some(&sounds), &storage, output.as_ref().map(|o| &**o));
+        }
+
+        let since_click = elapsed - beat as f32 * Calibration::period();
+        let scale = if since_click < FLASH_SECONDS {
+            3.0
+        } else {
+            1.0
+        };
+        if let Some(ball) = self.ball {
+            if let Some(transform) = data.world.write_storage::<Transform>().get_mut(ball) {
+                transform.set_scale(Vector3::new(scale, scale, 1.0));
+            }
+        }
+        Trans::None
+    }
+}
+
+fn signed_seconds(later: Instant, earlier: Instant) -> f32 {
+    if later >= earlier {
+        (later - earlier).as_secs_f32()
+    } else {
+        -(earlier - later).as_secs_f32()
+    }
+}
+
+/// Puts the new offset in the world and writes it to `config/calibration.ron`.
+fn save_offset(world: &mut World, offset: AudioOffset) {
+    info!("Measured an audio offset of {:.3}s", offset.seconds);
+    let written = application_root_dir()
+        .map_err(amethyst::Error::from)
+        .and_then(|root| Ok(offset.write(root.join("config/calibration.ron"))?));
+    if let Err(e) = written {
+        error!("Could not save the audio offset: {}", e);
+    }
+    world.insert(offset);
+}
+
+/// The ball that flashes on the clicks, in the middle of the arena.
+fn initialise_flash(world: &mut World, sprite_sheet_handle: Handle<SpriteSheet>) -> Entity {
+    let arena = world.read_resource::<GameConfig>().arena.clone();
+    let mut transform = Transform::default();
+    transform.set_translation_xyz(arena.width / 2.0, arena.height / 2.0, 0.5);
+
+    world
+        .create_entity()
+        .with(SpriteRender {
+            sprite_sheet: sprite_sheet_handle,
+            sprite_number: 1,
+        })
+        .with(transform)
+        .build()
+}
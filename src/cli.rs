@@ -0,0 +1,342 @@
+use crate::{
+    beats::{BeatsConfig, DetectorKind},
+    controller::{Controller, ControllersConfig},
+    rhythm::TimingWindows,
+    score::{MatchRules, WinCondition},
+    Side,
+};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::path::{Path, PathBuf};
+
+const SIDES: [(&str, Side); 4] = [
+    ("left", Side::Left),
+    ("right", Side::Right),
+    ("top", Side::Top),
+    ("bottom", Side::Bottom),
+];
+
+/// What the game was asked to do on the command line.
+pub enum Command {
+    Play(PlayOptions),
+    /// Finds the beats of a track and writes its beatmap, without playing.
+    Analyze(AnalyzeOptions),
+}
+
+/// Settings of a game given on the command line, which win over the
+/// configuration files.
+#[derive(Default)]
+pub struct PlayOptions {
+    /// A track to play straight away, skipping the menus.
+    pub track: Option<PathBuf>,
+    /// A beatmap to play the track with.
+    pub beatmap: Option<PathBuf>,
+    pub win: Option<WinCondition>,
+    /// Grades every contact by how close to a beat it is.
+    pub rhythm: bool,
+    /// Sends the ball to the left and right on downbeats.
+    pub route_downbeats: bool,
+    pub controllers: Vec<(Side, Controller)>,
+    /// The display configuration, instead of `config/display.ron`.
+    pub display: Option<PathBuf>,
+    /// Simulates the game without a window, as the `empty` feature does.
+    pub headless: bool,
+    pub detector: Option<DetectorKind>,
+}
+
+impl PlayOptions {
+    /// Overrides the match rules with the mode of the command line.
+    pub fn apply_rules(&self, rules: &mut MatchRules) {
+        if let Some(win) = &self.win {
+            rules.win = win.clone();
+        }
+        if self.rhythm && rules.rhythm.is_none() {
+            rules.rhythm = Some(TimingWindows::default());
+        }
+        if self.route_downbeats {
+            rules.route_downbeats = true;
+        }
+    }
+
+    /// Overrides the controllers of the paddles picked on the command line.
+    pub fn apply_controllers(&self, controllers: &mut ControllersConfig) {
+        for (side, controller) in &self.controllers {
+            let slot = match side {
+                Side::Left => &mut controllers.left,
+                Side::Right => &mut controllers.right,
+                Side::Top => &mut controllers.top,
+                Side::Bottom => &mut controllers.bottom,
+            };
+            *slot = controller.clone();
+        }
+    }
+
+    /// Overrides the beat detector with the one of the command line.
+    pub fn apply_detector(&self, beats: &mut BeatsConfig) {
+        override_detector(&self.detector, beats);
+    }
+}
+
+pub struct AnalyzeOptions {
+    pub track: PathBuf,
+    /// Where to write the beats, each in the format of its extension, instead
+    /// of the beatmap in `assets/beatmaps/<track name>.ron`.
+    pub outputs: Vec<PathBuf>,
+    /// Where to render the track with a click on every beat, as WAV.
+    pub clicks: Option<PathBuf>,
+    pub detector: Option<DetectorKind>,
+    /// Plays the track through the streaming tracker in real time instead,
+    /// printing the beats as they come.
+    pub realtime: bool,
+}
+
+impl AnalyzeOptions {
+    /// Overrides the beat detector with the one of the command line.
+    pub fn apply_detector(&self, beats: &mut BeatsConfig) {
+        override_detector(&self.detector, beats);
+    }
+}
+
+fn override_detector(detector: &Option<DetectorKind>, beats: &mut BeatsConfig) {
+    if let Some(detector) = detector {
+        beats.detector = detector.clone();
+    }
+}
+
+/// Reads the command line. Invalid arguments, `--help` and `--version`
+/// print their message and exit.
+pub fn parse() -> Command {
+    let detector = Arg::with_name("detector")
+        .long("detector")
+        .value_name("DETECTOR")
+        .help("Beat detector: `native`, `madmom`, `grid`, `stream` or `file:<beats.txt>`")
+        .validator(|value| parse_detector(&value).map(|_| ()));
+    let mut play = App::new("beat-bouncer")
+        .about("Pong that bounces on the beats of the music")
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .arg(
+            Arg::with_name("track")
+                .value_name("TRACK")
+                .help("Ogg or WAV file to play, skipping the menus"),
+        )
+        .arg(
+            Arg::with_name("beatmap")
+                .long("beatmap")
+                .value_name("FILE")
+                .requires("track")
+                .help("Beatmap to play the track with, instead of finding its beats"),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .long("mode")
+                .value_name("MODE")
+                .help("When the match ends: `first-to:<points>`, `timed:<seconds>` or `survive`")
+                .validator(|value| parse_mode(&value).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("rhythm")
+                .long("rhythm")
+                .help("Grades every contact by how close to a beat it is"),
+        )
+        .arg(
+            Arg::with_name("downbeats")
+                .long("downbeats")
+                .help("Sends the ball left and right on downbeats, up and down on other beats"),
+        )
+        .arg(
+            Arg::with_name("display")
+                .long("display")
+                .value_name("FILE")
+                .help("Display configuration, instead of config/display.ron"),
+        )
+        .arg(
+            Arg::with_name("headless")
+                .long("headless")
+                .help("Simulates the game without a window and logs it"),
+        )
+        .arg(detector.clone())
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("Finds the beats of a track and writes its beatmap, and prints its tempo")
+                .arg(
+                    Arg::with_name("track")
+                        .value_name("TRACK")
+                        .required(true)
+                        .help("Ogg or WAV file to analyse"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help(
+                            "Where to write the beats, instead of the beatmap in assets/beatmaps: \
+                             a .csv list, or a .json or .ron beatmap",
+                        )
+                        .validator(|value| {
+                            ExportFormat::of(Path::new(&value))
+                                .map(|_| ())
+                                .ok_or_else(|| {
+                                    format!("`{}` is not a .csv, .json or .ron file", value)
+                                })
+                        }),
+                )
+                .arg(
+                    Arg::with_name("clicks")
+                        .long("clicks")
+                        .value_name("FILE")
+                        .help("Renders the track with a click on every beat to a WAV file"),
+                )
+                .arg(Arg::with_name("realtime").long("realtime").help(
+                    "Streams the track through the beat tracker in real time and prints \
+                             the beats as they come, with how late they are",
+                ))
+                .arg(detector),
+        );
+    for (name, _) in SIDES.iter() {
+        play = play.arg(
+            Arg::with_name(*name)
+                .long(*name)
+                .value_name("CONTROLLER")
+                .help("Who moves the paddle: `human[:<axis>]`, `ai` or `autopilot`")
+                .validator(|value| parse_controller(&value, "").map(|_| ())),
+        );
+    }
+    let matches = play.get_matches();
+
+    match matches.subcommand() {
+        ("analyze", Some(analyze)) => Command::Analyze(AnalyzeOptions {
+            track: analyze.value_of("track").map(PathBuf::from).unwrap(),
+            outputs: analyze
+                .values_of("output")
+                .map_or_else(Vec::new, |outputs| outputs.map(PathBuf::from).collect()),
+            clicks: analyze.value_of("clicks").map(PathBuf::from),
+            detector: detector_of(analyze),
+            realtime: analyze.is_present("realtime"),
+        }),
+        _ => Command::Play(PlayOptions {
+            track: matches.value_of("track").map(PathBuf::from),
+            beatmap: matches.value_of("beatmap").map(PathBuf::from),
+            win: matches
+                .value_of("mode")
+                .map(|mode| parse_mode(mode).unwrap()),
+            rhythm: matches.is_present("rhythm"),
+            route_downbeats: matches.is_present("downbeats"),
+            controllers: SIDES
+                .iter()
+                .filter_map(|(name, side)| {
+                    let value = matches.value_of(name)?;
+                    let axis = format!("{}_paddle", name);
+                    Some((*side, parse_controller(value, &axis).unwrap()))
+                })
+                .collect(),
+            display: matches.value_of("display").map(PathBuf::from),
+            headless: matches.is_present("headless"),
+            detector: detector_of(&matches),
+        }),
+    }
+}
+
+/// How `analyze` writes the beats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One beat per line, with the interval since the one before.
+    Csv,
+    /// A beatmap, which the game loads with the `json` feature.
+    Json,
+    /// A beatmap.
+    Ron,
+}
+
+impl ExportFormat {
+    /// The format of a file, by its extension.
+    pub fn of(path: &Path) -> Option<ExportFormat> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "json" => Some(ExportFormat::Json),
+            "ron" => Some(ExportFormat::Ron),
+            _ => None,
+        }
+    }
+}
+
+/// The detector of the command line. It was validated while parsing.
+fn detector_of(matches: &ArgMatches) -> Option<DetectorKind> {
+    matches
+        .value_of("detector")
+        .map(|detector| parse_detector(detector).unwrap())
+}
+
+fn parse_detector(value: &str) -> Result<DetectorKind, String> {
+    match value {
+        "native" => Ok(DetectorKind::Native),
+        "madmom" => Ok(DetectorKind::Madmom),
+        "grid" => Ok(DetectorKind::Grid),
+        "stream" => Ok(DetectorKind::Stream),
+        _ if value.starts_with("file:") => Ok(DetectorKind::File(value[5..].to_owned())),
+        _ => Err(format!("unknown detector `{}`", value)),
+    }
+}
+
+fn parse_mode(value: &str) -> Result<WinCondition, String> {
+    let mut parts = value.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some("first-to"), Some(points)) => points
+            .parse()
+            .map(WinCondition::FirstTo)
+            .map_err(|_| format!("`{}` is not a number of points", points)),
+        (Some("timed"), Some(seconds)) => seconds
+            .parse()
+            .map(WinCondition::Timed)
+            .map_err(|_| format!("`{}` is not a number of seconds", seconds)),
+        (Some("survive"), None) => Ok(WinCondition::SurviveSong),
+        _ => Err(format!("unknown mode `{}`", value)),
+    }
+}
+
+/// A human plays with `default_axis` unless another axis is given.
+fn parse_controller(value: &str, default_axis: &str) -> Result<Controller, String> {
+    match value {
+        "human" => Ok(Controller::Human(default_axis.to_owned())),
+        "ai" => Ok(Controller::Ai),
+        "autopilot" => Ok(Controller::BeatAutopilot),
+        _ if value.starts_with("human:") => Ok(Controller::Human(value[6..].to_owned())),
+        _ => Err(format!("unknown controller `{}`", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mode_reads_every_win_condition() {
+        assert_eq!(parse_mode("first-to:3"), Ok(WinCondition::FirstTo(3)));
+        assert_eq!(parse_mode("timed:90"), Ok(WinCondition::Timed(90.0)));
+        assert_eq!(parse_mode("survive"), Ok(WinCondition::SurviveSong));
+        assert!(parse_mode("first-to:many").is_err());
+        assert!(parse_mode("timed").is_err());
+        assert!(parse_mode("survive:3").is_err());
+        assert!(parse_mode("sudden-death").is_err());
+    }
+
+    #[test]
+    fn parse_controller_defaults_the_axis_of_humans() {
+        assert_eq!(
+            parse_controller("human", "left_paddle"),
+            Ok(Controller::Human("left_paddle".to_owned()))
+        );
+        assert_eq!(
+            parse_controller("human:stick", "left_paddle"),
+            Ok(Controller::Human("stick".to_owned()))
+        );
+        assert_eq!(parse_controller("ai", ""), Ok(Controller::Ai));
+        assert_eq!(
+            parse_controller("autopilot", ""),
+            Ok(Controller::BeatAutopilot)
+        );
+        assert!(parse_controller("robot", "").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use amethyst::{config::Config, Error};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Sizes, speeds and sounds of the game, read from `config/game.ron` so they
+/// can be tuned without recompiling. Every value left out of the file keeps
+/// its default.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub arena: ArenaConfig,
+    pub paddle: PaddleConfig,
+    pub ball: BallConfig,
+    pub physics: PhysicsConfig,
+    pub audio: SoundConfig,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ArenaConfig {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for ArenaConfig {
+    fn default() -> ArenaConfig {
+        ArenaConfig {
+            width: 100.0,
+            height: 100.0,
+        }
+    }
+}
+
+/// The size of the paddles as they stand on the left and right; the top and
+/// bottom ones are turned on their side.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PaddleConfig {
+    pub width: f32,
+    pub height: f32,
+    /// Speed of a paddle moved by a player, with the axis all the way.
+    pub speed: f32,
+    /// Fastest a paddle moved by the AI goes.
+    pub ai_speed: f32,
+}
+
+impl Default for PaddleConfig {
+    fn default() -> PaddleConfig {
+        PaddleConfig {
+            width: 4.0,
+            height: 16.0,
+            speed: 60.0,
+            ai_speed: 45.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BallConfig {
+    /// Velocity the ball starts with.
+    pub velocity: [f32; 2],
+    pub radius: f32,
+    /// Fastest the ball goes to land a bounce on a beat.
+    pub max_velocity: f32,
+    /// Seconds from the start of a match until the ball comes in.
+    pub spawn_delay: f32,
+}
+
+impl Default for BallConfig {
+    fn default() -> BallConfig {
+        BallConfig {
+            velocity: [60.0, 50.0],
+            radius: 2.0,
+            max_velocity: 250.0,
+            spawn_delay: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    /// Seconds simulated by every physics step.
+    pub step: f32,
+    /// Most physics steps run in one frame.
+    pub max_steps: u32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> PhysicsConfig {
+        PhysicsConfig {
+            step: 1.0 / 120.0,
+            max_steps: 8,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SoundConfig {
+    /// Played when the ball bounces, from the assets.
+    pub bounce: String,
+    /// Played when a paddle misses, from the assets.
+    pub score: String,
+    /// Volume of the music, 1.0 being as loud as the track.
+    pub music_volume: f32,
+}
+
+impl Default for SoundConfig {
+    fn default() -> SoundConfig {
+        SoundConfig {
+            bounce: "audio/beat.wav".to_owned(),
+            score: "audio/confirm.ogg".to_owned(),
+            // Music is a bit loud, reduce the volume.
+            music_volume: 0.25,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Reads the configuration at `path`, and checks it makes a playable
+    /// game with the sounds of `assets_dir`. Every problem found is in the
+    /// error.
+    pub fn read(path: &Path, assets_dir: &Path) -> amethyst::Result<GameConfig> {
+        let config = GameConfig::load_no_fallback(path)
+            .map_err(|e| Error::from_string(format!("Could not read {:?}: {}", path, e)))?;
+        let problems = config.problems(assets_dir);
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(Error::from_string(format!(
+                "Invalid game configuration in {:?}:\n  {}",
+                path,
+                problems.join("\n  ")
+            )))
+        }
+    }
+
+    /// What is wrong with the configuration, if anything.
+    pub fn problems(&self, assets_dir: &Path) -> Vec<String> {
+        let mut problems = vec![];
+        let mut positive = |name: &str, value: f32| {
+            if !(value.is_finite() && value > 0.0) {
+                problems.push(format!("`{}` must be more than 0, not {}", name, value));
+            }
+        };
+        positive("arena.width", self.arena.width);
+        positive("arena.height", self.arena.height);
+        positive("paddle.width", self.paddle.width);
+        positive("paddle.height", self.paddle.height);
+        positive("paddle.speed", self.paddle.speed);
+        positive("paddle.ai_speed", self.paddle.ai_speed);
+        positive("ball.radius", self.ball.radius);
+        positive("ball.max_velocity", self.ball.max_velocity);
+        positive("physics.step", self.physics.step);
+
+        let shortest_side = self.arena.width.min(self.arena.height);
+        if self.paddle.height > shortest_side {
+            problems.push(format!(
+                "`paddle.height` of {} does not fit in the arena, which is {} across",
+                self.paddle.height, shortest_side
+            ));
+        }
+        if (self.paddle.width + self.ball.radius) * 2.0 >= shortest_side {
+            problems.push(format!(
+                "`paddle.width` of {} and `ball.radius` of {} leave no room to play in an \
+                 arena {} across",
+                self.paddle.width, self.ball.radius, shortest_side
+            ));
+        }
+        if self.ball.velocity.iter().any(|v| !v.is_finite()) {
+            problems.push(format!(
+                "`ball.velocity` must be finite, not {:?}",
+                self.ball.velocity
+            ));
+        }
+        if !(self.ball.spawn_delay.is_finite() && self.ball.spawn_delay >= 0.0) {
+            problems.push(format!(
+                "`ball.spawn_delay` must be 0 or more, not {}",
+                self.ball.spawn_delay
+            ));
+        }
+        if self.physics.max_steps == 0 {
+            problems.push("`physics.max_steps` must be at least 1".to_owned());
+        }
+        if !(self.audio.music_volume.is_finite() && self.audio.music_volume >= 0.0) {
+            problems.push(format!(
+                "`audio.music_volume` must be 0 or more, not {}",
+                self.audio.music_volume
+            ));
+        }
+        for (name, file) in &[
+            ("audio.bounce", &self.audio.bounce),
+            ("audio.score", &self.audio.score),
+        ] {
+            if !assets_dir.join(file).is_file() {
+                problems.push(format!(
+                    "`{}` is not a file of the assets: {:?}",
+                    name, file
+                ));
+            }
+        }
+        problems
+    }
+}
@@ -0,0 +1,44 @@
+use crate::Side;
+use serde::{Deserialize, Serialize};
+
+/// Who moves a paddle.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Controller {
+    /// A player, through the named axis of the input bindings.
+    Human(String),
+    /// The computer, following the ball about as fast as a player could.
+    Ai,
+    /// The computer, always meeting the ball exactly when it arrives, which
+    /// is on a beat.
+    BeatAutopilot,
+}
+
+impl Default for Controller {
+    fn default() -> Controller {
+        Controller::BeatAutopilot
+    }
+}
+
+/// The controller of each paddle, read from `config/controllers.ron`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ControllersConfig {
+    #[serde(default)]
+    pub left: Controller,
+    #[serde(default)]
+    pub right: Controller,
+    #[serde(default)]
+    pub top: Controller,
+    #[serde(default)]
+    pub bottom: Controller,
+}
+
+impl ControllersConfig {
+    pub fn of(&self, side: Side) -> Controller {
+        match side {
+            Side::Left => self.left.clone(),
+            Side::Right => self.right.clone(),
+            Side::Top => self.top.clone(),
+            Side::Bottom => self.bottom.clone(),
+        }
+    }
+}
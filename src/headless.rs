@@ -0,0 +1,335 @@
+use crate::{
+    audio::{AudioOffset, MusicFile, PlaybackClock},
+    beatmap::Beatmap,
+    beats::{self, BeatsConfig, FeatureFrame, MusicFeatures},
+    bundle::{physics_dispatcher, run_physics_steps},
+    cli::PlayOptions,
+    config::{GameConfig, PhysicsConfig},
+    controller::{Controller, ControllersConfig},
+    physics::FixedTimestep,
+    pong::{initialise_ball, initialise_paddles, insert_beatmap},
+    score::{MatchRules, Score},
+    timeline::BeatTimeline,
+    Ball, Paddle, Side, AUDIO_MUSIC,
+};
+use amethyst::{
+    config::Config,
+    core::transform::Transform,
+    ecs::prelude::{Dispatcher, DispatcherBuilder, Join, World, WorldExt},
+    error::Error,
+    utils::application_root_dir,
+};
+use log::{error, info};
+
+/// Step of the simulation when it is run from the command line: 60 updates
+/// per second, like the game.
+const STEP_SECONDS: f32 = 1.0 / 60.0;
+/// How long the command line simulation plays.
+const SIMULATED_SECONDS: f32 = 60.0;
+
+/// Where a ball is and where it is going.
+#[derive(Clone, Copy, Debug)]
+pub struct BallState {
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+}
+
+/// Where a paddle is and how fast it moves along its side.
+#[derive(Clone, Copy, Debug)]
+pub struct PaddleState {
+    pub side: Side,
+    pub position: [f32; 2],
+    pub velocity: f32,
+}
+
+/// The pong systems without a window or audio. The `PongBundle` runs on its
+/// own world, one physics step of `dt` per `step`, while the music is
+/// assumed to play along perfectly; the state of the balls and paddles can
+/// be read after every step.
+pub struct Simulation<'a, 'b> {
+    world: World,
+    dispatcher: Dispatcher<'a, 'b>,
+    dt: f32,
+    elapsed: f32,
+}
+
+impl<'a, 'b> Simulation<'a, 'b> {
+    /// Sets up the paddles and a ball as `config` says, ready to bounce on
+    /// the beats of `beatmap` as the `rules` say. Without input, the
+    /// `controllers` can't include a human.
+    pub fn new(
+        beatmap: Beatmap,
+        config: GameConfig,
+        rules: MatchRules,
+        controllers: ControllersConfig,
+        dt: f32,
+    ) -> amethyst::Result<Simulation<'a, 'b>> {
+        for side in &[Side::Left, Side::Right, Side::Top, Side::Bottom] {
+            if let Controller::Human(_) = controllers.of(*side) {
+                let side = format!("{:?}", side).to_lowercase();
+                return Err(Error::from_string(format!(
+                    "The {} paddle is played by a human, who can't play headless; \
+                     pick another controller for it, e.g. with `--{} ai`",
+                    side, side
+                )));
+            }
+        }
+        let mut world = World::new();
+        let dispatcher = physics_dispatcher(&mut world, DispatcherBuilder::new())?;
+
+        insert_beatmap(&mut world, beatmap);
+        world.insert(AudioOffset::default());
+        world.insert(MusicFeatures::default());
+        world.insert(PlaybackClock {
+            track: Some(0),
+            ..Default::default()
+        });
+        world.insert(FixedTimestep::new(&PhysicsConfig {
+            step: dt,
+            ..config.physics.clone()
+        }));
+        world.insert(config);
+        world.insert(rules);
+
+        initialise_paddles(&mut world, None, &controllers);
+        initialise_ball(&mut world, None);
+
+        Ok(Simulation {
+            world,
+            dispatcher,
+            dt,
+            elapsed: 0.0,
+        })
+    }
+
+    /// Advances the game by one `dt`.
+    pub fn step(&mut self) {
+        self.frame(self.dt);
+    }
+
+    /// Advances the game by a frame of `delta_seconds`, as the `Pong` state
+    /// does: the physics steps that fit in it run first, then the clock
+    /// moves on. At the end of the track the clock starts over, as it does
+    /// when the DJ loops the song.
+    pub fn frame(&mut self, delta_seconds: f32) {
+        self.elapsed += delta_seconds;
+        run_physics_steps(&self.world, &mut self.dispatcher, delta_seconds);
+        {
+            let duration = self.world.read_resource::<BeatTimeline>().duration(0);
+            let mut clock = self.world.write_resource::<PlaybackClock>();
+            clock.position += delta_seconds;
+            if let Some(duration) = duration.filter(|duration| clock.position >= *duration) {
+                clock.position -= duration;
+                clock.loops += 1;
+            }
+        }
+        self.world.maintain();
+    }
+
+    /// Steps until `seconds` more have been simulated.
+    pub fn run_for(&mut self, seconds: f32) {
+        let steps = (seconds / self.dt).round() as usize;
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+
+    /// Seconds simulated so far.
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    pub fn balls(&self) -> Vec<BallState> {
+        let balls = self.world.read_storage::<Ball>();
+        let transforms = self.world.read_storage::<Transform>();
+        (&balls, &transforms)
+            .join()
+            .map(|(ball, transform)| BallState {
+                position: [transform.translation().x, transform.translation().y],
+                velocity: ball.velocity,
+            })
+            .collect()
+    }
+
+    /// Makes the balls and paddles play to a track that sounds like
+    /// `features`.
+    pub fn insert_features(&mut self, features: MusicFeatures) {
+        self.world.insert(features);
+    }
+
+    /// How the music sounds at the current position of the track.
+    pub fn music(&self) -> FeatureFrame {
+        let position = self.world.read_resource::<PlaybackClock>().position;
+        self.world.read_resource::<MusicFeatures>().at(position)
+    }
+
+    pub fn score(&self) -> Score {
+        (*self.world.read_resource::<Score>()).clone()
+    }
+
+    pub fn paddles(&self) -> Vec<PaddleState> {
+        let paddles = self.world.read_storage::<Paddle>();
+        let transforms = self.world.read_storage::<Transform>();
+        (&paddles, &transforms)
+            .join()
+            .map(|(paddle, transform)| PaddleState {
+                side: paddle.side,
+                position: [transform.translation().x, transform.translation().y],
+                velocity: paddle.velocity,
+            })
+            .collect()
+    }
+}
+
+/// Plays the bundled track, or the one of the command line, headless for a
+/// minute and logs where the ball and the paddles are, and how the music
+/// sounds, every second. This is
+/// what the game runs with `--headless` or when it is built with the `empty`
+/// feature.
+pub fn run(options: &PlayOptions) -> amethyst::Result<()> {
+    let app_root = application_root_dir()?;
+    let config = GameConfig::read(&app_root.join("config/game.ron"), &app_root.join("assets"))?;
+    let mut beats_config = BeatsConfig::load(app_root.join("config/beats.ron"));
+    options.apply_detector(&mut beats_config);
+    let track = match &options.track {
+        Some(track) => track.clone(),
+        None => MusicFile::asset(AUDIO_MUSIC)?.path(),
+    };
+    let beatmap = match &options.beatmap {
+        Some(beatmap) => Beatmap::load_no_fallback(beatmap)?,
+        None => Beatmap::detect(&track.to_string_lossy(), &track, &beats_config),
+    };
+
+    let mut rules = MatchRules::load(app_root.join("config/rules.ron"));
+    options.apply_rules(&mut rules);
+    let mut controllers = ControllersConfig::load(app_root.join("config/controllers.ron"));
+    options.apply_controllers(&mut controllers);
+
+    let mut simulation = Simulation::new(beatmap, config, rules, controllers, STEP_SECONDS)?;
+    match beats::find_features(&track.to_string_lossy(), beats_config.sample_rate) {
+        Ok(features) => simulation.insert_features(features),
+        Err(e) => error!("Could not analyse {:?}: {}", track, e),
+    }
+    while simulation.elapsed() < SIMULATED_SECONDS {
+        simulation.run_for(1.0);
+        info!("{:.1}s: {:?}", simulation.elapsed(), simulation.balls());
+        info!("{:.1}s: {:?}", simulation.elapsed(), simulation.paddles());
+        info!("{:.1}s: {:?}", simulation.elapsed(), simulation.score());
+        info!("{:.1}s: {:?}", simulation.elapsed(), simulation.music());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::ContactEvent;
+    use amethyst::shrev::EventChannel;
+
+    const PERIOD: f32 = 0.5;
+
+    fn simulation(dt: f32) -> Simulation<'static, 'static> {
+        let beatmap = Beatmap {
+            bpm: 60.0 / PERIOD,
+            beats: (0..120).map(|beat| beat as f32 * PERIOD).collect(),
+            ..Default::default()
+        };
+        Simulation::new(
+            beatmap,
+            GameConfig::default(),
+            MatchRules::default(),
+            ControllersConfig::default(),
+            dt,
+        )
+        .unwrap()
+    }
+
+    /// How far `time` is from the nearest beat.
+    fn off_beat(time: f32) -> f32 {
+        let off = time % PERIOD;
+        off.min(PERIOD - off)
+    }
+
+    #[test]
+    fn contacts_land_on_beats() {
+        let mut simulation = simulation(STEP_SECONDS);
+        let mut contacts = vec![];
+        while simulation.elapsed() < 20.0 {
+            let before = simulation.balls();
+            simulation.step();
+            for (before, after) in before.iter().zip(simulation.balls()) {
+                // Only the left and right paddles send the ball back the
+                // other way across, and a served ball starts in the middle.
+                let turned = before.velocity[0].signum() != after.velocity[0].signum();
+                if turned && (after.position[0] - 50.0).abs() > 30.0 {
+                    contacts.push(simulation.elapsed());
+                }
+            }
+        }
+
+        assert!(contacts.len() >= 5, "{:?}", contacts);
+        // The ball is served without aiming at a beat, and every contact
+        // after the first sends it to the next one, which it reaches within
+        // the step.
+        for contact in &contacts[1..] {
+            assert!(
+                off_beat(*contact) <= STEP_SECONDS + 1e-4,
+                "contact at {} s",
+                contact
+            );
+        }
+    }
+
+    #[test]
+    fn contacts_land_on_beats_with_several_steps_per_frame() {
+        // Steps and frames that add up exactly, so no time is left over.
+        let dt = 1.0 / 64.0;
+        let mut simulation = simulation(dt);
+        let mut reader = simulation
+            .world
+            .fetch_mut::<EventChannel<ContactEvent>>()
+            .register_reader();
+        let radius = GameConfig::default().ball.radius;
+        let mut contacts = vec![];
+        while simulation.elapsed() < 20.0 {
+            simulation.frame(4.0 * dt);
+            let ball = simulation.balls()[0];
+            let events = simulation
+                .world
+                .fetch::<EventChannel<ContactEvent>>()
+                .read(&mut reader)
+                .cloned()
+                .collect::<Vec<_>>();
+            for event in events {
+                if let Side::Left | Side::Right = event.side {
+                    // The centre of the ball was a radius away from the
+                    // paddle, and it went straight on since.
+                    let centre = event.point[0] + radius * ball.velocity[0].signum();
+                    let since = (ball.position[0] - centre) / ball.velocity[0];
+                    contacts.push(simulation.elapsed() - since);
+                }
+            }
+        }
+
+        assert!(contacts.len() >= 5, "{:?}", contacts);
+        for contact in &contacts[1..] {
+            assert!(off_beat(*contact) < 1e-3, "contact at {} s", contact);
+        }
+    }
+
+    #[test]
+    fn humans_cant_play_headless() {
+        let controllers = ControllersConfig {
+            top: Controller::Human("top_paddle".to_owned()),
+            ..Default::default()
+        };
+        let simulation = Simulation::new(
+            Beatmap::default(),
+            GameConfig::default(),
+            MatchRules::default(),
+            controllers,
+            STEP_SECONDS,
+        );
+        assert!(simulation.is_err());
+    }
+}
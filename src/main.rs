@@ -1,13 +1,30 @@
 //! Pong
 
+mod analyze;
 mod audio;
+mod beatmap;
 mod beats;
 mod bundle;
+mod calibration;
+mod cli;
+mod config;
+mod controller;
+mod headless;
+mod menu;
+mod pause;
+mod physics;
 mod pong;
+mod results;
+mod rhythm;
+mod score;
+mod song_select;
 mod systems;
+mod timeline;
 
 use amethyst::{
-    audio::{AudioBundle, DjSystemDesc},
+    assets::{Directory, Processor},
+    audio::AudioBundle,
+    config::Config,
     core::{frame_limiter::FrameRateLimitStrategy, transform::TransformBundle},
     ecs::{Component, DenseVecStorage},
     input::{InputBundle, StringBindings},
@@ -21,29 +38,76 @@ use amethyst::{
     utils::application_root_dir,
 };
 
-use crate::{audio::Music, bundle::PongBundle};
-use std::time::Duration;
-
-const ARENA_HEIGHT: f32 = 100.0;
-const ARENA_WIDTH: f32 = 100.0;
-const PADDLE_HEIGHT: f32 = 16.0;
-const PADDLE_WIDTH: f32 = 4.0;
-const PADDLE_VELOCITY: f32 = 0.0;
+use crate::{
+    audio::{AudioOffset, Music, MusicFile},
+    beatmap::Beatmap,
+    beats::BeatsConfig,
+    cli::{Command, PlayOptions},
+    config::GameConfig,
+    controller::{Controller, ControllersConfig},
+    menu::MainMenu,
+    pong::Pong,
+    score::MatchRules,
+    song_select::MusicConfig,
+    systems::{DjSystem, InterpolatePoseSystem, PlaybackClockSystem},
+};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-const BALL_VELOCITY_X: f32 = 60.0;
-const BALL_VELOCITY_Y: f32 = 50.0;
-const BALL_RADIUS: f32 = 2.0;
+/// Sound effects that come with the game, which are not listed as songs.
+const AUDIO_EFFECTS: [&str; 3] = ["audio/beat.wav", "audio/bounce.ogg", "audio/confirm.ogg"];
 
-const AUDIO_BOUNCE: &str = "audio/beat.wav";
+const FONT: &str = "font/square.ttf";
+const AUDIO_MUSIC: &str = "audio/Computer_Music_All-Stars_-_Wheres_My_Jetpack.ogg";
+/// Name of the `Loader` source of the directory of a track given on the
+/// command line.
+const TRACK_SOURCE: &str = "track";
 
 fn main() -> amethyst::Result<()> {
     amethyst::start_logger(Default::default());
 
-    use crate::pong::Pong;
+    let options = match cli::parse() {
+        Command::Analyze(options) => return analyze::run(&options),
+        Command::Play(options) => options,
+    };
+    // Without a renderer there is nothing to show, so the game is simulated
+    // instead.
+    if cfg!(feature = "empty") || options.headless {
+        return headless::run(&options);
+    }
 
+    // A track of the command line is played straight away.
+    match track_of(&options) {
+        Some(track) => play(Pong::new(track), &options),
+        None => play(MainMenu::default(), &options),
+    }
+}
+
+/// The track of the command line, read through the `TRACK_SOURCE`.
+fn track_of(options: &PlayOptions) -> Option<MusicFile> {
+    let track = options.track.as_ref()?;
+    let directory = match track.parent() {
+        Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    Some(MusicFile {
+        audio_file: track.file_name()?.to_string_lossy().into_owned(),
+        directory,
+        source: Some(TRACK_SOURCE.to_owned()),
+        beatmap: options.beatmap.clone(),
+    })
+}
+
+/// Opens the game window, starting in `initial_state`.
+fn play<S: SimpleState + 'static>(initial_state: S, options: &PlayOptions) -> amethyst::Result<()> {
     let app_root = application_root_dir()?;
 
-    let display_config_path = app_root.join("config/display.ron");
+    let display_config_path = options
+        .display
+        .clone()
+        .unwrap_or_else(|| app_root.join("config/display.ron"));
 
     let key_bindings_path = {
         if cfg!(feature = "sdl_controller") {
@@ -55,18 +119,35 @@ fn main() -> amethyst::Result<()> {
 
     let assets_dir = app_root.join("assets/");
 
+    // A broken game configuration is reported rather than played with.
+    let game_config = GameConfig::read(&app_root.join("config/game.ron"), &assets_dir)?;
+    let mut beats_config = BeatsConfig::load(app_root.join("config/beats.ron"));
+    options.apply_detector(&mut beats_config);
+    let audio_offset = AudioOffset::load(app_root.join("config/calibration.ron"));
+    let mut controllers = ControllersConfig::load(app_root.join("config/controllers.ron"));
+    options.apply_controllers(&mut controllers);
+    let mut rules = MatchRules::load(app_root.join("config/rules.ron"));
+    options.apply_rules(&mut rules);
+    let music = MusicConfig::load(app_root.join("config/music.ron"));
+
     let game_data = GameDataBuilder::default()
+        // The physics is stepped by the `Pong` state; this draws the entities in between
+        // steps, and has to run before their transforms are updated.
+        .with(InterpolatePoseSystem, "interpolate_pose", &[])
         // Add the transform bundle which handles tracking entity positions
         .with_bundle(TransformBundle::new())?
         .with_bundle(
             InputBundle::<StringBindings>::new().with_bindings_from_file(key_bindings_path)?,
         )?
-        .with_bundle(PongBundle)?
+        .with(Processor::<Beatmap>::new(), "beatmap_processor", &[])
         .with_bundle(AudioBundle::default())?
-        .with_system_desc(
-            DjSystemDesc::new(|music: &mut Music| music.music.next()),
-            "dj_system",
-            &[],
+        .with(DjSystem, "dj_system", &[])
+        // The physics steps of a frame run before the game data, so they read the clock
+        // where this left it the frame before.
+        .with(
+            PlaybackClockSystem::default(),
+            "playback_clock",
+            &["dj_system"],
         )
         .with_bundle(UiBundle::<StringBindings>::new())?
         .with_bundle(
@@ -81,7 +162,19 @@ fn main() -> amethyst::Result<()> {
                 .with_plugin(RenderUi::default()),
         )?;
 
-    let mut game = Application::build(assets_dir, Pong::default())?
+    let mut builder = Application::build(assets_dir, initial_state)?;
+    if let Some(track) = track_of(options) {
+        builder = builder.with_source(TRACK_SOURCE, Directory::new(track.directory));
+    }
+    let mut game = builder
+        .with_resource(game_config)
+        .with_resource(beats_config)
+        .with_resource(audio_offset)
+        .with_resource(controllers)
+        .with_resource(rules)
+        .with_resource(music)
+        // Nothing plays on the menus, until a song is picked.
+        .with_resource(Music::default())
         .with_frame_limit(
             FrameRateLimitStrategy::SleepAndYield(Duration::from_millis(2)),
             100,
@@ -101,7 +194,7 @@ impl Component for Ball {
     type Storage = DenseVecStorage<Self>;
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Side {
     Left,
     Right,
@@ -114,6 +207,7 @@ pub struct Paddle {
     pub side: Side,
     pub width: f32,
     pub height: f32,
+    pub controller: Controller,
 }
 
 impl Paddle {
@@ -123,6 +217,7 @@ impl Paddle {
             side,
             width: 1.0,
             height: 1.0,
+            controller: Controller::default(),
         }
     }
 }
@@ -0,0 +1,136 @@
+use crate::{config::GameConfig, pong::load_font, song_select::SongSelect};
+use amethyst::{
+    assets::{AssetStorage, Directory, Handle, Loader},
+    core::{math::Vector3, transform::Transform},
+    ecs::prelude::{Entity, World},
+    input::InputEvent,
+    prelude::*,
+    renderer::{Camera, ImageFormat, SpriteRender, SpriteSheet, SpriteSheetFormat, Texture},
+    ui::{Anchor, UiText, UiTransform},
+    utils::application_root_dir,
+};
+use log::error;
+
+/// Name of the `Loader` source of the `resources` directory.
+const RESOURCES_SOURCE: &str = "resources";
+/// The logo is drawn 230 pixels wide; this fits it in the top of the arena.
+const LOGO_SCALE: f32 = 0.2;
+
+/// The title screen, where the game starts.
+#[derive(Default)]
+pub struct MainMenu {
+    entities: Vec<Entity>,
+}
+
+impl SimpleState for MainMenu {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let StateData { world, .. } = data;
+        let arena = world.read_resource::<GameConfig>().arena.clone();
+        match load_logo(world) {
+            Ok(logo) => {
+                let mut transform = Transform::default();
+                transform.set_translation_xyz(arena.width * 0.5, arena.height * 0.7, 0.0);
+                transform.set_scale(Vector3::new(LOGO_SCALE, LOGO_SCALE, 1.0));
+                let logo = world
+                    .create_entity()
+                    .with(SpriteRender {
+                        sprite_sheet: logo,
+                        sprite_number: 0,
+                    })
+                    .with(transform)
+                    .build();
+                self.entities.push(logo);
+
+                let mut transform = Transform::default();
+                transform.set_translation_xyz(arena.width * 0.5, arena.height * 0.5, 1.0);
+                let camera = world
+                    .create_entity()
+                    .with(Camera::standard_2d(arena.width, arena.height))
+                    .with(transform)
+                    .build();
+                self.entities.push(camera);
+            }
+            Err(e) => error!("Could not load the logo: {}", e),
+        }
+
+        self.entities.extend(show_lines(
+            world,
+            vec![
+                ("play", "Press Enter to play".to_owned(), -100.0),
+                ("quit", "Press Escape to quit".to_owned(), -150.0),
+            ],
+        ));
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        if let Err(e) = data.world.delete_entities(&self.entities) {
+            error!("Could not remove the title screen: {}", e);
+        }
+        self.entities.clear();
+    }
+
+    fn handle_event(
+        &mut self,
+        _data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        match event {
+            StateEvent::Input(InputEvent::ActionPressed(action)) => match action.as_str() {
+                "select" => Trans::Switch(Box::new(SongSelect::default())),
+                "cancel" => Trans::Quit,
+                _ => Trans::None,
+            },
+            _ => Trans::None,
+        }
+    }
+}
+
+/// Loads the logo from `resources/sprites`, which is outside the assets.
+fn load_logo(world: &mut World) -> amethyst::Result<Handle<SpriteSheet>> {
+    let resources = application_root_dir()?.join("resources");
+    world
+        .write_resource::<Loader>()
+        .add_source(RESOURCES_SOURCE, Directory::new(resources));
+
+    let loader = world.read_resource::<Loader>();
+    let texture = loader.load_from(
+        "sprites/logo.png",
+        ImageFormat::default(),
+        RESOURCES_SOURCE,
+        (),
+        &world.read_resource::<AssetStorage<Texture>>(),
+    );
+    Ok(loader.load_from(
+        "sprites/logo.ron",
+        SpriteSheetFormat(texture),
+        RESOURCES_SOURCE,
+        (),
+        &world.read_resource::<AssetStorage<SpriteSheet>>(),
+    ))
+}
+
+/// Shows lines of text in the middle of the screen, each `y` above it, and
+/// returns their entities.
+pub fn show_lines(world: &mut World, lines: Vec<(&str, String, f32)>) -> Vec<Entity> {
+    let font = load_font(world);
+    lines
+        .into_iter()
+        .map(|(id, text, y)| {
+            let transform = UiTransform::new(
+                id.to_owned(),
+                Anchor::Middle,
+                Anchor::Middle,
+                0.,
+                y,
+                1.,
+                600.,
+                50.,
+            );
+            world
+                .create_entity()
+                .with(transform)
+                .with(UiText::new(font.clone(), text, [1., 1., 1., 1.], 40.))
+                .build()
+        })
+        .collect()
+}
@@ -0,0 +1,55 @@
+use crate::{menu::show_lines, song_select::SongSelect};
+use amethyst::{audio::AudioSink, ecs::prelude::Entity, input::InputEvent, prelude::*};
+use log::error;
+
+/// Shown over the game while it is paused. The game stops updating while
+/// this is on top of it, and the music is paused with it.
+#[derive(Default)]
+pub struct Pause {
+    texts: Vec<Entity>,
+}
+
+impl SimpleState for Pause {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let StateData { world, .. } = data;
+        if let Some(sink) = world.try_fetch::<AudioSink>() {
+            sink.pause();
+        }
+        self.texts = show_lines(
+            world,
+            vec![
+                ("paused", "Paused".to_owned(), 50.0),
+                ("resume", "Press Enter to resume".to_owned(), -50.0),
+                ("leave", "Press Escape to leave the song".to_owned(), -100.0),
+            ],
+        );
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        if let Some(sink) = data.world.try_fetch::<AudioSink>() {
+            sink.play();
+        }
+        if let Err(e) = data.world.delete_entities(&self.texts) {
+            error!("Could not remove the pause screen: {}", e);
+        }
+        self.texts.clear();
+    }
+
+    fn handle_event(
+        &mut self,
+        _data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        match event {
+            StateEvent::Input(InputEvent::ActionPressed(action)) => match action.as_str() {
+                "select" => Trans::Pop,
+                "cancel" => Trans::Sequence(vec![
+                    Trans::Pop,
+                    Trans::Switch(Box::new(SongSelect::default())),
+                ]),
+                _ => Trans::None,
+            },
+            _ => Trans::None,
+        }
+    }
+}
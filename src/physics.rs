@@ -0,0 +1,149 @@
+use crate::{config::PhysicsConfig, Side};
+use amethyst::ecs::{Component, DenseVecStorage, Entity};
+
+/// The physics runs in steps of a fixed length, whatever the frame rate, so
+/// the same inputs always play out the same way. Every frame adds its time
+/// to the accumulator and the `Pong` state runs as many steps as fit in it.
+pub struct FixedTimestep {
+    /// Seconds simulated by every step.
+    pub step: f32,
+    /// Most steps run in one frame. After a long stall the time left over is
+    /// dropped, rather than catching up on all of it at once.
+    pub max_steps: u32,
+    /// Position of the music at the start of the step being run, in seconds
+    /// on the time of the `PlaybackClock`. The clock only moves once a
+    /// frame, so every step of a frame is told its own time here.
+    pub time: f32,
+    accumulator: f32,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> FixedTimestep {
+        FixedTimestep::new(&PhysicsConfig::default())
+    }
+}
+
+impl FixedTimestep {
+    pub fn new(config: &PhysicsConfig) -> FixedTimestep {
+        FixedTimestep {
+            step: config.step,
+            max_steps: config.max_steps,
+            time: 0.0,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Adds the time of a frame, and returns how many steps to run for it.
+    pub fn advance(&mut self, delta_seconds: f32) -> u32 {
+        self.accumulator += delta_seconds;
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            if steps == self.max_steps {
+                self.accumulator %= self.step;
+                break;
+            }
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Adds the time of a frame that starts with the music at `position`,
+    /// and returns how many steps to run for it, like `advance`. The steps
+    /// pick up where the last frame left off, behind the music by the time
+    /// that was left over.
+    pub fn start_frame(&mut self, position: f32, delta_seconds: f32) -> u32 {
+        self.time = position - self.accumulator;
+        self.advance(delta_seconds)
+    }
+
+    /// Moves `time` on to the start of the next step.
+    pub fn next_step(&mut self) {
+        self.time += self.step;
+    }
+
+    /// How far the frame is from the last step towards the next one, between
+    /// 0 and 1.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.step
+    }
+}
+
+/// Where an entity was after the last two physics steps. Its `Transform` is
+/// drawn in between the two, so the motion stays smooth when the frame rate
+/// and the step don't line up.
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicsPose {
+    pub previous: [f32; 2],
+    pub current: [f32; 2],
+}
+
+impl PhysicsPose {
+    pub fn at(x: f32, y: f32) -> PhysicsPose {
+        PhysicsPose {
+            previous: [x, y],
+            current: [x, y],
+        }
+    }
+}
+
+impl Component for PhysicsPose {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// A ball touching a paddle. The `BounceSystem` writes one to the
+/// `EventChannel<ContactEvent>` for every bounce.
+#[derive(Clone, Copy, Debug)]
+pub struct ContactEvent {
+    pub ball: Entity,
+    /// Side of the paddle that was hit.
+    pub side: Side,
+    /// Where the ball touched the paddle.
+    pub point: [f32; 2],
+    /// Index in the playlist of the track playing at the contact.
+    pub track: usize,
+    /// Position of the music at the contact, in seconds, on the time of the
+    /// `BeatTimeline`.
+    pub time: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestep() -> FixedTimestep {
+        FixedTimestep::new(&PhysicsConfig {
+            step: 0.25,
+            max_steps: 3,
+        })
+    }
+
+    #[test]
+    fn advance_runs_the_steps_that_fit_and_keeps_the_rest() {
+        let mut timestep = timestep();
+        assert_eq!(timestep.advance(0.625), 2);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(0.125), 1);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(0.0625), 0);
+        assert_eq!(timestep.alpha(), 0.25);
+    }
+
+    #[test]
+    fn advance_drops_the_time_past_max_steps() {
+        let mut timestep = timestep();
+        assert_eq!(timestep.advance(2.125), 3);
+        assert_eq!(timestep.alpha(), 0.5);
+    }
+
+    #[test]
+    fn frames_start_behind_the_music_by_the_time_left_over() {
+        let mut timestep = timestep();
+        assert_eq!(timestep.start_frame(10.0, 0.625), 2);
+        assert_eq!(timestep.time, 10.0);
+        timestep.next_step();
+        assert_eq!(timestep.time, 10.25);
+        assert_eq!(timestep.start_frame(10.625, 0.125), 1);
+        assert_eq!(timestep.time, 10.5);
+    }
+}
@@ -1,61 +1,196 @@
-use crate::{audio::MusicFile, beats, Ball, Paddle, Side, ARENA_HEIGHT, ARENA_WIDTH};
+use crate::{
+    audio::{AudioOffset, Music, MusicFile, PlaybackClock},
+    beatmap::Beatmap,
+    beats::{
+        BeatStream, BeatsConfig, DetectorKind, FeaturesJob, MusicFeatures, StreamDetector,
+        StreamEvent,
+    },
+    bundle::{physics_dispatcher, run_physics_steps},
+    calibration::Calibration,
+    config::GameConfig,
+    controller::ControllersConfig,
+    pause::Pause,
+    physics::{FixedTimestep, PhysicsPose},
+    results::Results,
+    rhythm::{RhythmScore, RhythmText},
+    score::{MatchRules, Score, ScoreText},
+    timeline::BeatTimeline,
+    Ball, Paddle, Side, FONT,
+};
+#[cfg(feature = "json")]
+use amethyst::assets::JsonFormat;
 use amethyst::{
-    assets::{AssetStorage, Handle, Loader},
-    core::{timing::Time, transform::Transform},
-    ecs::prelude::World,
+    assets::{AssetStorage, Handle, Loader, ProgressCounter, RonFormat},
+    audio::{output::Output, AudioSink},
+    config::Config,
+    core::{timing::Time, transform::Transform, ArcThreadPool},
+    ecs::prelude::{
+        Component, Dispatcher, DispatcherBuilder, EntityBuilder, Join, NullStorage, World,
+    },
+    input::InputEvent,
     prelude::*,
     renderer::{Camera, ImageFormat, SpriteRender, SpriteSheet, SpriteSheetFormat, Texture},
+    ui::{Anchor, FontHandle, TtfFormat, UiText, UiTransform},
+    utils::application_root_dir,
 };
-use std::{iter::Cycle, vec::IntoIter};
+use log::error;
+use std::path::Path;
 
-#[derive(Default)]
 pub struct Pong {
+    music: MusicFile,
     ball_spawn_timer: Option<f32>,
     sprite_sheet_handle: Option<Handle<SpriteSheet>>,
+    beatmap: Option<(Handle<Beatmap>, ProgressCounter)>,
+    /// Runs the `PongBundle`, one `FixedTimestep` step at a time.
+    physics: Option<Dispatcher<'static, 'static>>,
 }
 
-impl std::default::Default for Intervals {
-    fn default() -> Self {
-        let empty: Vec<f32> = vec![];
-        Intervals {
-            intervals: empty.into_iter().cycle(),
+impl Pong {
+    /// A game played to `music`.
+    pub fn new(music: MusicFile) -> Pong {
+        Pong {
+            music,
+            ball_spawn_timer: None,
+            sprite_sheet_handle: None,
+            beatmap: None,
+            physics: None,
         }
     }
 }
 
-pub struct Intervals {
-    pub intervals: Cycle<IntoIter<f32>>,
-}
-
 impl SimpleState for Pong {
     fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
         let StateData { world, .. } = data;
         use crate::audio::initialise_audio;
 
-        // Wait one second before spawning the ball.
-        self.ball_spawn_timer.replace(1.0);
+        let config = (*world.read_resource::<GameConfig>()).clone();
+        // Give the players a moment before spawning the ball.
+        self.ball_spawn_timer.replace(config.ball.spawn_delay);
+
+        // Nothing is left of a match played before.
+        world.insert(Score::default());
+        world.insert(RhythmScore::default());
+        world.insert(PlaybackClock::default());
+        world.insert(FixedTimestep::new(&config.physics));
+        insert_beatmap(world, Beatmap::default());
+
+        let pool = (*world.read_resource::<ArcThreadPool>()).clone();
+        match physics_dispatcher(world, DispatcherBuilder::new().with_pool(pool)) {
+            Ok(physics) => self.physics = Some(physics),
+            Err(e) => error!("Could not set up the physics: {}", e),
+        }
 
         // Load the spritesheet necessary to render the graphics.
         // `spritesheet` is the layout of the sprites on the image;
         // `texture` is the pixel data.
         self.sprite_sheet_handle.replace(load_sprite_sheet(world));
-        initialise_paddles(world, self.sprite_sheet_handle.clone().unwrap());
+        let controllers = (*world.read_resource::<ControllersConfig>()).clone();
+        initialise_paddles(world, self.sprite_sheet_handle.clone(), &controllers);
         initialise_camera(world);
-        world.insert({
-            MusicFile {
-                audio_file: "audio/Computer_Music_All-Stars_-_Wheres_My_Jetpack.ogg",
-            }
-        });
+        initialise_scoreboard(world);
+        world.insert(self.music.clone());
         initialise_audio(world);
-        //let beats =
-        //    beats::find_beats("assets/audio/Computer_Music_All-Stars_-_Wheres_My_Jetpack.ogg")
-        //        .unwrap();
-        //world.insert(Intervals {
-        //    intervals: beats.intervals.into_iter().cycle(),
-        //});
+        // A hand-made beatmap wins over beat detection.
+        if let Some(file) = self.music.beatmap.clone() {
+            match Beatmap::load_no_fallback(&file) {
+                Ok(beatmap) => insert_beatmap(world, beatmap),
+                Err(e) => {
+                    error!("Could not load the beatmap {:?}: {}", file, e);
+                    insert_detected_beatmap(world);
+                }
+            }
+        } else {
+            self.beatmap = load_beatmap(world);
+            if self.beatmap.is_none() {
+                insert_detected_beatmap(world);
+            }
+        }
+        // Streamed beats come with the features of the track.
+        if !world.has_value::<FeaturesJob>() {
+            start_music_features(world);
+        }
+    }
+
+    /// Leaves nothing of the match behind: its entities are deleted, and the
+    /// music stops.
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let StateData { world, .. } = data;
+        let match_entities = {
+            let entities = world.entities();
+            let markers = world.read_storage::<MatchEntity>();
+            (&entities, &markers)
+                .join()
+                .map(|(entity, _)| entity)
+                .collect::<Vec<_>>()
+        };
+        if let Err(e) = world.delete_entities(&match_entities) {
+            error!("Could not delete the entities of the match: {}", e);
+        }
+        world.remove::<ScoreText>();
+        world.remove::<RhythmText>();
+        // With an empty playlist the DJ starts nothing more.
+        world.insert(Music::default());
+        world.remove::<BeatStream>();
+        world.remove::<FeaturesJob>();
+        // Dropping the sink stops what it was playing.
+        let sink = world
+            .try_fetch::<Output>()
+            .map(|output| AudioSink::new(&output));
+        if let Some(sink) = sink {
+            world.insert(sink);
+        }
+        self.physics = None;
+        self.beatmap = None;
+        self.ball_spawn_timer = None;
+    }
+
+    /// Back from the `Calibration`, the beats of the match move to the
+    /// offset it measured.
+    fn on_resume(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let StateData { world, .. } = data;
+        let offset = world
+            .try_fetch::<AudioOffset>()
+            .map_or_else(AudioOffset::default, |offset| *offset);
+        world.write_resource::<BeatTimeline>().set_offset(&offset);
+    }
+
+    fn handle_event(
+        &mut self,
+        _data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        match (event, &self.sprite_sheet_handle) {
+            (StateEvent::Input(InputEvent::ActionPressed(action)), Some(sprite_sheet))
+                if action == "calibrate" =>
+            {
+                Trans::Push(Box::new(Calibration::new(sprite_sheet.clone())))
+            }
+            (StateEvent::Input(InputEvent::ActionPressed(action)), _) if action == "cancel" => {
+                Trans::Push(Box::new(Pause::default()))
+            }
+            _ => Trans::None,
+        }
     }
 
     fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        poll_beat_stream(data.world);
+        poll_music_features(data.world);
+        if let Some((handle, progress)) = self.beatmap.take() {
+            if progress.num_failed() > 0 {
+                error!("Could not load the beatmap, detecting the beats instead");
+                insert_detected_beatmap(data.world);
+            } else {
+                let loaded = data
+                    .world
+                    .read_resource::<AssetStorage<Beatmap>>()
+                    .get(&handle)
+                    .cloned();
+                match loaded {
+                    Some(beatmap) => insert_beatmap(data.world, beatmap),
+                    None => self.beatmap = Some((handle, progress)),
+                }
+            }
+        }
         if let Some(mut timer) = self.ball_spawn_timer.take() {
             // If the timer isn't expired yet, substract the time that passed since last update.
             {
@@ -64,16 +199,144 @@ impl SimpleState for Pong {
             }
             if timer <= 0.0 {
                 // When timer expire, spawn the ball
-                initialise_ball(data.world, self.sprite_sheet_handle.clone().unwrap());
+                initialise_ball(data.world, self.sprite_sheet_handle.clone());
             } else {
                 // If timer is not expired yet, put it back onto the state.
                 self.ball_spawn_timer.replace(timer);
             }
         }
+
+        let delta_seconds = data.world.read_resource::<Time>().delta_seconds();
+        if let Some(physics) = self.physics.as_mut() {
+            run_physics_steps(data.world, physics, delta_seconds);
+        }
+
+        let over = data.world.read_resource::<MatchRules>().is_over(
+            &data.world.read_resource::<Score>(),
+            &data.world.read_resource::<PlaybackClock>(),
+        );
+        if over {
+            return Trans::Push(Box::new(Results::default()));
+        }
         Trans::None
     }
 }
 
+/// Starts loading the beatmap of the current `MusicFile` from
+/// `assets/beatmaps/<track name>.ron`, if there is one. This is where the
+/// beatmaps of the tracks of the user music directory go too.
+fn load_beatmap(world: &World) -> Option<(Handle<Beatmap>, ProgressCounter)> {
+    let audio_file = world.read_resource::<MusicFile>().audio_file.clone();
+    let name = Path::new(&audio_file)
+        .file_stem()?
+        .to_string_lossy()
+        .into_owned();
+    let assets_dir = application_root_dir().ok()?.join("assets");
+
+    let loader = world.read_resource::<Loader>();
+    let storage = world.read_resource::<AssetStorage<Beatmap>>();
+    let mut progress = ProgressCounter::new();
+
+    let ron_map = format!("beatmaps/{}.ron", name);
+    if assets_dir.join(&ron_map).is_file() {
+        let handle = loader.load(ron_map, RonFormat, &mut progress, &storage);
+        return Some((handle, progress));
+    }
+    #[cfg(feature = "json")]
+    {
+        let json_map = format!("beatmaps/{}.json", name);
+        if assets_dir.join(&json_map).is_file() {
+            let handle = loader.load(json_map, JsonFormat, &mut progress, &storage);
+            return Some((handle, progress));
+        }
+    }
+    None
+}
+
+/// Detects the beats of the current `MusicFile` and inserts them as its
+/// `Beatmap`. With the `Stream` detector the match starts on an empty one
+/// instead, and the beats are added to the `BeatTimeline` as the
+/// `BeatStream` finds them, and the `FeaturesJob` of the track is fed from
+/// the same decoder.
+fn insert_detected_beatmap(world: &mut World) {
+    let (audio, path) = {
+        let music = world.read_resource::<MusicFile>();
+        (music.audio_file.clone(), music.path())
+    };
+    let config = (*world.read_resource::<BeatsConfig>()).clone();
+    if let DetectorKind::Stream = config.detector {
+        match StreamDetector::default().stream_with_features(&path.to_string_lossy(), false) {
+            Ok((stream, features)) => {
+                insert_beatmap(world, Beatmap::default());
+                world.insert(stream);
+                world.insert(MusicFeatures::default());
+                world.insert(features);
+                return;
+            }
+            Err(e) => error!("Could not stream the beats of {:?}: {}", path, e),
+        }
+    }
+    insert_beatmap(world, Beatmap::detect(&audio, &path, &config));
+}
+
+/// Adds the beats the `BeatStream` found since the last frame to the
+/// `BeatTimeline`, if the beats are streamed.
+fn poll_beat_stream(world: &World) {
+    let events = match world.try_fetch::<BeatStream>() {
+        Some(stream) => stream.poll(),
+        None => return,
+    };
+    let mut timeline = world.write_resource::<BeatTimeline>();
+    for event in events {
+        match event {
+            StreamEvent::Beat(beat) => timeline.push_beat(0, beat),
+            StreamEvent::End(duration) => timeline.set_duration(0, duration),
+        }
+    }
+}
+
+/// Starts analysing how the current `MusicFile` sounds over time. Its
+/// `MusicFeatures` are silent until the analysis is done, and stay so when
+/// the track can't be decoded.
+fn start_music_features(world: &mut World) {
+    let job = {
+        let music = world.read_resource::<MusicFile>();
+        let config = world.read_resource::<BeatsConfig>();
+        FeaturesJob::start(&music.path().to_string_lossy(), config.sample_rate)
+    };
+    world.insert(MusicFeatures::default());
+    world.insert(job);
+}
+
+/// Inserts the `MusicFeatures` of the current track once its analysis is
+/// done.
+fn poll_music_features(world: &mut World) {
+    let polled = match world.try_fetch::<FeaturesJob>() {
+        Some(job) => job.poll(),
+        None => return,
+    };
+    match polled {
+        Some(Ok(features)) => world.insert(features),
+        Some(Err(e)) => error!(
+            "Could not analyse {:?}: {}",
+            world.read_resource::<MusicFile>().path(),
+            e
+        ),
+        None => return,
+    }
+    world.remove::<FeaturesJob>();
+}
+
+/// Makes `beatmap` the one the game plays to, the only track of the
+/// playlist.
+pub fn insert_beatmap(world: &mut World, beatmap: Beatmap) {
+    let offset = world
+        .try_fetch::<AudioOffset>()
+        .map_or_else(AudioOffset::default, |offset| *offset);
+    world.insert(BeatTimeline::new(&[beatmap.clone()], &offset));
+    world.insert(beatmap);
+}
+
 fn load_sprite_sheet(world: &mut World) -> Handle<SpriteSheet> {
     // Load the sprite sheet necessary to render the graphics.
     // The texture is the pixel data
@@ -101,22 +364,115 @@ fn load_sprite_sheet(world: &mut World) -> Handle<SpriteSheet> {
     )
 }
 
+/// Loads the font of all the text of the game.
+pub fn load_font(world: &World) -> FontHandle {
+    world
+        .read_resource::<Loader>()
+        .load(FONT, TtfFormat, (), &world.read_resource())
+}
+
+/// Initialises the misses of each side, next to its paddle.
+fn initialise_scoreboard(world: &mut World) {
+    let font = load_font(world);
+    let mut score_text = |id: &str, anchor: Anchor, x: f32, y: f32| {
+        let transform = UiTransform::new(id.to_owned(), anchor, anchor, x, y, 1., 100., 50.);
+        create_match_entity(world)
+            .with(transform)
+            .with(UiText::new(
+                font.clone(),
+                "0".to_owned(),
+                [1., 1., 1., 1.],
+                50.,
+            ))
+            .build()
+    };
+    let score_text = ScoreText {
+        left: score_text("left_score", Anchor::MiddleLeft, 50., 0.),
+        right: score_text("right_score", Anchor::MiddleRight, -50., 0.),
+        // The top paddle is at the bottom of the screen, and the bottom one at the top.
+        top: score_text("top_score", Anchor::BottomMiddle, 0., 50.),
+        bottom: score_text("bottom_score", Anchor::TopMiddle, 0., -50.),
+    };
+    world.insert(score_text);
+
+    if world.read_resource::<MatchRules>().rhythm.is_some() {
+        let transform = UiTransform::new(
+            "judgement".to_owned(),
+            Anchor::TopMiddle,
+            Anchor::TopMiddle,
+            0.,
+            -100.,
+            1.,
+            400.,
+            50.,
+        );
+        let judgement = create_match_entity(world)
+            .with(transform)
+            .with(UiText::new(font, String::new(), [1., 1., 1., 1.], 40.))
+            .build();
+        world.insert(RhythmText { judgement });
+    }
+}
+
 /// Initialise the camera.
 fn initialise_camera(world: &mut World) {
     // Setup camera in a way that our screen covers whole arena and (0, 0) is in the bottom left.
+    let arena = world.read_resource::<GameConfig>().arena.clone();
     let mut transform = Transform::default();
-    transform.set_translation_xyz(ARENA_WIDTH * 0.5, ARENA_HEIGHT * 0.5, 1.0);
+    transform.set_translation_xyz(arena.width * 0.5, arena.height * 0.5, 1.0);
 
-    world
-        .create_entity()
-        .with(Camera::standard_2d(ARENA_WIDTH, ARENA_HEIGHT))
+    create_match_entity(world)
+        .with(Camera::standard_2d(arena.width, arena.height))
         .with(transform)
         .build();
 }
 
-/// Initialises one paddle on the left, and one paddle on the right.
-fn initialise_paddles(world: &mut World, sprite_sheet_handle: Handle<SpriteSheet>) {
-    use crate::{PADDLE_HEIGHT, PADDLE_VELOCITY, PADDLE_WIDTH};
+/// Marks the entities of a match, which are deleted when it stops. The
+/// entities of the other states are left alone.
+#[derive(Default)]
+struct MatchEntity;
+
+impl Component for MatchEntity {
+    type Storage = NullStorage<Self>;
+}
+
+/// Starts building an entity of the match.
+fn create_match_entity(world: &mut World) -> EntityBuilder<'_> {
+    world.register::<MatchEntity>();
+    world.create_entity().with(MatchEntity)
+}
+
+/// Gives an entity a sprite of the sheet, when there is a sheet to render it
+/// with; the headless simulation has none.
+fn with_sprite<'a>(
+    builder: EntityBuilder<'a>,
+    sprite_sheet_handle: &Option<Handle<SpriteSheet>>,
+    sprite_number: usize,
+) -> EntityBuilder<'a> {
+    match sprite_sheet_handle {
+        Some(sprite_sheet) => builder.with(SpriteRender {
+            sprite_sheet: sprite_sheet.clone(),
+            sprite_number,
+        }),
+        None => builder,
+    }
+}
+
+/// The physics pose of an entity standing still at its transform.
+fn pose_of(transform: &Transform) -> PhysicsPose {
+    PhysicsPose::at(transform.translation().x, transform.translation().y)
+}
+
+/// Initialises one paddle on each side of the arena, each moved by its
+/// controller.
+pub fn initialise_paddles(
+    world: &mut World,
+    sprite_sheet_handle: Option<Handle<SpriteSheet>>,
+    controllers: &ControllersConfig,
+) {
+    let config = (*world.read_resource::<GameConfig>()).clone();
+    let (arena_width, arena_height) = (config.arena.width, config.arena.height);
+    let (paddle_width, paddle_height) = (config.paddle.width, config.paddle.height);
 
     let mut left_transform = Transform::default();
     let mut right_transform = Transform::default();
@@ -124,95 +480,82 @@ fn initialise_paddles(world: &mut World, sprite_sheet_handle: Handle<SpriteSheet
     let mut bottom_transform = Transform::default();
 
     // Correctly position the paddles.
-    let y = ARENA_HEIGHT / 2.0;
-    left_transform.set_translation_xyz(PADDLE_WIDTH * 0.5, y, 0.0);
-    right_transform.set_translation_xyz(ARENA_WIDTH - PADDLE_WIDTH * 0.5, y, 0.0);
-    bottom_transform.set_translation_xyz(ARENA_WIDTH * 0.5, ARENA_HEIGHT - PADDLE_WIDTH * 0.5, 0.0);
-    top_transform.set_translation_xyz(ARENA_WIDTH * 0.5, PADDLE_WIDTH * 0.5, 0.0);
-
-    // Assign the sprites for the paddles
-    let sprite_render_vertical = SpriteRender {
-        sprite_sheet: sprite_sheet_handle.clone(),
-        sprite_number: 0,
-    };
+    let y = arena_height / 2.0;
+    left_transform.set_translation_xyz(paddle_width * 0.5, y, 0.0);
+    right_transform.set_translation_xyz(arena_width - paddle_width * 0.5, y, 0.0);
+    bottom_transform.set_translation_xyz(arena_width * 0.5, arena_height - paddle_width * 0.5, 0.0);
+    top_transform.set_translation_xyz(arena_width * 0.5, paddle_width * 0.5, 0.0);
 
-    let sprite_render_horizontal = SpriteRender {
-        sprite_sheet: sprite_sheet_handle,
-        sprite_number: 2,
-    };
+    // The vertical paddles are the first sprite, the horizontal ones the third.
+    let vertical = 0;
+    let horizontal = 2;
 
     // Create a left plank entity.
-    world
-        .create_entity()
-        .with(sprite_render_vertical.clone())
+    with_sprite(create_match_entity(world), &sprite_sheet_handle, vertical)
         .with(Paddle {
-            velocity: PADDLE_VELOCITY,
+            velocity: 0.0,
             side: Side::Left,
-            width: PADDLE_WIDTH,
-            height: PADDLE_HEIGHT,
+            width: paddle_width,
+            height: paddle_height,
+            controller: controllers.of(Side::Left),
         })
+        .with(pose_of(&left_transform))
         .with(left_transform)
         .build();
 
     // Create right plank entity.
-    world
-        .create_entity()
-        .with(sprite_render_vertical)
+    with_sprite(create_match_entity(world), &sprite_sheet_handle, vertical)
         .with(Paddle {
             velocity: 0.0,
             side: Side::Right,
-            width: PADDLE_WIDTH,
-            height: PADDLE_HEIGHT,
+            width: paddle_width,
+            height: paddle_height,
+            controller: controllers.of(Side::Right),
         })
+        .with(pose_of(&right_transform))
         .with(right_transform)
         .build();
     // Create top plank entity
-    world
-        .create_entity()
-        .with(sprite_render_horizontal.clone())
+    with_sprite(create_match_entity(world), &sprite_sheet_handle, horizontal)
         .with(Paddle {
             velocity: 0.0,
             side: Side::Top,
-            width: PADDLE_HEIGHT,
-            height: PADDLE_WIDTH,
+            width: paddle_height,
+            height: paddle_width,
+            controller: controllers.of(Side::Top),
         })
+        .with(pose_of(&top_transform))
         .with(top_transform)
         .build();
     // Create bottom plank entity
-    world
-        .create_entity()
-        .with(sprite_render_horizontal)
+    with_sprite(create_match_entity(world), &sprite_sheet_handle, horizontal)
         .with(Paddle {
             velocity: 0.0,
             side: Side::Bottom,
-            width: PADDLE_HEIGHT,
-            height: PADDLE_WIDTH,
+            width: paddle_height,
+            height: paddle_width,
+            controller: controllers.of(Side::Bottom),
         })
+        .with(pose_of(&bottom_transform))
         .with(bottom_transform)
         .build();
 }
 
 /// Initialises one ball in the middle-ish of the arena.
-fn initialise_ball(world: &mut World, sprite_sheet_handle: Handle<SpriteSheet>) {
-    use crate::{BALL_RADIUS, BALL_VELOCITY_X, BALL_VELOCITY_Y};
+pub fn initialise_ball(world: &mut World, sprite_sheet_handle: Option<Handle<SpriteSheet>>) {
+    let config = (*world.read_resource::<GameConfig>()).clone();
 
     // Create the translation.
     let mut local_transform = Transform::default();
-    local_transform.set_translation_xyz(ARENA_WIDTH / 2.0, ARENA_HEIGHT / 2.0, 0.0);
+    local_transform.set_translation_xyz(config.arena.width / 2.0, config.arena.height / 2.0, 0.0);
 
-    // Assign the sprite for the ball
-    let sprite_render = SpriteRender {
-        sprite_sheet: sprite_sheet_handle,
-        sprite_number: 1, // ball is the second sprite on the sprite_sheet
-    };
-
-    world
-        .create_entity()
-        .with(sprite_render)
+    // ball is the second sprite on the sprite_sheet
+    with_sprite(create_match_entity(world), &sprite_sheet_handle, 1)
         .with(Ball {
-            radius: BALL_RADIUS,
-            velocity: [BALL_VELOCITY_X, BALL_VELOCITY_Y],
+            radius: config.ball.radius,
+            velocity: config.ball.velocity,
         })
+        .with(pose_of(&local_transform))
         .with(local_transform)
         .build();
 }
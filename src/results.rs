@@ -0,0 +1,115 @@
+use crate::{
+    audio::{MusicFile, PlaybackClock},
+    menu::show_lines,
+    pong::Pong,
+    rhythm::RhythmScore,
+    score::{MatchRules, Score, WinCondition},
+    song_select::SongSelect,
+};
+use amethyst::{
+    audio::AudioSink,
+    ecs::prelude::{Entity, World},
+    input::InputEvent,
+    prelude::*,
+};
+use log::error;
+
+/// Shown over the game once the `MatchRules` say the match is over, with
+/// who won, the misses of each side and, in rhythm mode, the accuracy. The
+/// game and its music stop meanwhile. The song can be played again or
+/// another one picked.
+#[derive(Default)]
+pub struct Results {
+    texts: Vec<Entity>,
+}
+
+impl SimpleState for Results {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let StateData { world, .. } = data;
+        if let Some(sink) = world.try_fetch::<AudioSink>() {
+            sink.pause();
+        }
+        let mut lines = vec![
+            ("match_over", "Match over".to_owned(), 100.0),
+            ("result", result(world), 50.0),
+            ("misses", misses(world), 0.0),
+            ("retry", "Press Enter to play again".to_owned(), -100.0),
+            (
+                "back",
+                "Press Escape to pick another song".to_owned(),
+                -150.0,
+            ),
+        ];
+        if world.read_resource::<MatchRules>().rhythm.is_some() {
+            let rhythm = world.read_resource::<RhythmScore>();
+            let accuracy = format!(
+                "{:.1}% accuracy, {} points, {} max combo",
+                rhythm.accuracy(),
+                rhythm.points,
+                rhythm.max_combo
+            );
+            lines.push(("accuracy", accuracy, -50.0));
+        }
+        self.texts = show_lines(world, lines);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        if let Err(e) = data.world.delete_entities(&self.texts) {
+            error!("Could not remove the match results: {}", e);
+        }
+        self.texts.clear();
+    }
+
+    fn handle_event(
+        &mut self,
+        data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        match event {
+            StateEvent::Input(InputEvent::ActionPressed(action)) => match action.as_str() {
+                "select" => {
+                    let music = (*data.world.read_resource::<MusicFile>()).clone();
+                    Trans::Sequence(vec![Trans::Pop, Trans::Switch(Box::new(Pong::new(music)))])
+                }
+                "cancel" => Trans::Sequence(vec![
+                    Trans::Pop,
+                    Trans::Switch(Box::new(SongSelect::default())),
+                ]),
+                _ => Trans::None,
+            },
+            _ => Trans::None,
+        }
+    }
+}
+
+/// Who won, or whether the song was survived.
+fn result(world: &World) -> String {
+    let rules = world.read_resource::<MatchRules>();
+    let score = world.read_resource::<Score>();
+    let song_over = world.read_resource::<PlaybackClock>().loops > 0;
+    if let WinCondition::SurviveSong = rules.win {
+        if song_over && !rules.out_of_lives(&score) {
+            return "Song survived!".to_owned();
+        }
+    }
+    let leaders = score.leaders();
+    let names = leaders
+        .iter()
+        .map(|side| format!("{:?}", side))
+        .collect::<Vec<_>>()
+        .join(" and ");
+    if leaders.len() == 1 {
+        format!("{} wins", names)
+    } else {
+        format!("{} win", names)
+    }
+}
+
+/// How many times each side missed.
+fn misses(world: &World) -> String {
+    let score = world.read_resource::<Score>();
+    format!(
+        "Misses: left {}, right {}, top {}, bottom {}",
+        score.left, score.right, score.top, score.bottom
+    )
+}
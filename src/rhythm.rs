@@ -0,0 +1,134 @@
+use amethyst::ecs::Entity;
+use serde::{Deserialize, Serialize};
+
+/// How close to a beat a contact was.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Judgement {
+    Perfect,
+    Great,
+    Good,
+    Miss,
+}
+
+impl Judgement {
+    fn points(self) -> u64 {
+        match self {
+            Judgement::Perfect => 300,
+            Judgement::Great => 200,
+            Judgement::Good => 100,
+            Judgement::Miss => 0,
+        }
+    }
+}
+
+/// Largest gap between a contact and its nearest beat for each judgement, in
+/// milliseconds. Anything further off is a miss. Setting them in
+/// `config/rules.ron` turns on rhythm scoring.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TimingWindows {
+    pub perfect_ms: f32,
+    pub great_ms: f32,
+    pub good_ms: f32,
+}
+
+impl Default for TimingWindows {
+    fn default() -> TimingWindows {
+        TimingWindows {
+            perfect_ms: 30.0,
+            great_ms: 60.0,
+            good_ms: 100.0,
+        }
+    }
+}
+
+impl TimingWindows {
+    /// Grades a contact `error` seconds away from its beat.
+    pub fn judge(&self, error: f32) -> Judgement {
+        let error_ms = error.abs() * 1000.0;
+        if error_ms <= self.perfect_ms {
+            Judgement::Perfect
+        } else if error_ms <= self.great_ms {
+            Judgement::Great
+        } else if error_ms <= self.good_ms {
+            Judgement::Good
+        } else {
+            Judgement::Miss
+        }
+    }
+}
+
+/// Most the combo multiplies the points of a contact by.
+const MAX_MULTIPLIER: u64 = 4;
+/// Contacts in a row it takes to raise the multiplier by one.
+const COMBO_STEP: u32 = 10;
+
+/// The rhythm score of a song: how many contacts got each judgement, and the
+/// points they made. Every judgement but a miss adds to the combo, and the
+/// longer the combo the more points a contact is worth.
+#[derive(Clone, Debug, Default)]
+pub struct RhythmScore {
+    pub perfect: u32,
+    pub great: u32,
+    pub good: u32,
+    pub miss: u32,
+    pub combo: u32,
+    pub max_combo: u32,
+    pub points: u64,
+    pub last: Option<Judgement>,
+}
+
+impl RhythmScore {
+    pub fn record(&mut self, judgement: Judgement) {
+        match judgement {
+            Judgement::Perfect => self.perfect += 1,
+            Judgement::Great => self.great += 1,
+            Judgement::Good => self.good += 1,
+            Judgement::Miss => self.miss += 1,
+        }
+        if judgement == Judgement::Miss {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+        }
+        self.points += judgement.points() * self.multiplier();
+        self.last = Some(judgement);
+    }
+
+    /// What the points of the next contact are multiplied by.
+    pub fn multiplier(&self) -> u64 {
+        (1 + u64::from(self.combo / COMBO_STEP)).min(MAX_MULTIPLIER)
+    }
+
+    /// How close to the beats the contacts were, from 0 to 100: a perfect
+    /// counts fully, a great two thirds, and a good one third.
+    pub fn accuracy(&self) -> f32 {
+        let total = self.perfect + self.great + self.good + self.miss;
+        if total == 0 {
+            return 100.0;
+        }
+        let weighted = self.perfect as f32 * 3.0 + self.great as f32 * 2.0 + self.good as f32;
+        weighted / (total as f32 * 3.0) * 100.0
+    }
+}
+
+/// The text entity showing the last judgement and the combo.
+pub struct RhythmText {
+    pub judgement: Entity,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn judge_grades_by_the_distance_to_the_beat() {
+        let windows = TimingWindows::default();
+        assert_eq!(windows.judge(0.0), Judgement::Perfect);
+        assert_eq!(windows.judge(-0.02), Judgement::Perfect);
+        assert_eq!(windows.judge(0.05), Judgement::Great);
+        assert_eq!(windows.judge(-0.09), Judgement::Good);
+        assert_eq!(windows.judge(0.2), Judgement::Miss);
+        assert_eq!(windows.judge(-0.2), Judgement::Miss);
+    }
+}
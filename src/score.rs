@@ -0,0 +1,137 @@
+use crate::{audio::PlaybackClock, rhythm::TimingWindows, Side};
+use amethyst::ecs::Entity;
+use serde::{Deserialize, Serialize};
+
+const SIDES: [Side; 4] = [Side::Left, Side::Right, Side::Top, Side::Bottom];
+
+/// How many times each paddle let the ball through, and how long the match
+/// has been going on. The `WinnerSystem` keeps it.
+#[derive(Clone, Debug, Default)]
+pub struct Score {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+    /// Seconds played, counted in physics steps.
+    pub time: f32,
+}
+
+impl Score {
+    pub fn misses(&self, side: Side) -> u32 {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+            Side::Top => self.top,
+            Side::Bottom => self.bottom,
+        }
+    }
+
+    pub fn miss(&mut self, side: Side) {
+        match side {
+            Side::Left => self.left += 1,
+            Side::Right => self.right += 1,
+            Side::Top => self.top += 1,
+            Side::Bottom => self.bottom += 1,
+        }
+    }
+
+    /// Points of a side, one for every miss of the others.
+    pub fn points(&self, side: Side) -> u32 {
+        SIDES.iter().map(|side| self.misses(*side)).sum::<u32>() - self.misses(side)
+    }
+
+    /// The sides that missed the least.
+    pub fn leaders(&self) -> Vec<Side> {
+        let fewest = SIDES.iter().map(|side| self.misses(*side)).min();
+        SIDES
+            .iter()
+            .cloned()
+            .filter(|side| Some(self.misses(*side)) == fewest)
+            .collect()
+    }
+}
+
+/// A paddle let the ball through. The `WinnerSystem` writes one to the
+/// `EventChannel<PointEvent>` for every miss.
+#[derive(Clone, Copy, Debug)]
+pub struct PointEvent {
+    /// The side that missed.
+    pub missed: Side,
+}
+
+/// When a match ends.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum WinCondition {
+    /// As soon as a side has this many points.
+    FirstTo(u32),
+    /// After this many seconds.
+    Timed(f32),
+    /// When the song is over.
+    SurviveSong,
+}
+
+/// The rules of a match, read from `config/rules.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MatchRules {
+    pub win: WinCondition,
+    /// Misses a side can afford; the match ends when a side runs out.
+    #[serde(default)]
+    pub lives: Option<u32>,
+    /// Grades every contact by how close to a beat it is, when set.
+    #[serde(default)]
+    pub rhythm: Option<TimingWindows>,
+    /// Sends the ball to the left or right paddle when its next contact is
+    /// on a downbeat, and to the top or bottom one on the other beats.
+    #[serde(default)]
+    pub route_downbeats: bool,
+}
+
+impl Default for MatchRules {
+    fn default() -> MatchRules {
+        MatchRules {
+            win: WinCondition::FirstTo(5),
+            lives: None,
+            rhythm: None,
+            route_downbeats: false,
+        }
+    }
+}
+
+impl MatchRules {
+    /// Whether a side missed more often than it could afford.
+    pub fn out_of_lives(&self, score: &Score) -> bool {
+        self.lives.map_or(false, |lives| {
+            SIDES.iter().any(|side| score.misses(*side) >= lives)
+        })
+    }
+
+    pub fn is_over(&self, score: &Score, clock: &PlaybackClock) -> bool {
+        self.out_of_lives(score)
+            || match self.win {
+                WinCondition::FirstTo(points) => {
+                    SIDES.iter().any(|side| score.points(*side) >= points)
+                }
+                WinCondition::Timed(seconds) => score.time >= seconds,
+                WinCondition::SurviveSong => clock.loops > 0,
+            }
+    }
+}
+
+/// The text entities showing the misses of each side.
+pub struct ScoreText {
+    pub left: Entity,
+    pub right: Entity,
+    pub top: Entity,
+    pub bottom: Entity,
+}
+
+impl ScoreText {
+    pub fn of(&self, side: Side) -> Entity {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+            Side::Top => self.top,
+            Side::Bottom => self.bottom,
+        }
+    }
+}
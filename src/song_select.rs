@@ -0,0 +1,252 @@
+use crate::{
+    audio::MusicFile,
+    beats,
+    config::GameConfig,
+    menu::MainMenu,
+    pong::{load_font, Pong},
+    AUDIO_EFFECTS,
+};
+use amethyst::{
+    assets::{Directory, Loader},
+    ecs::prelude::{Entity, World},
+    input::InputEvent,
+    prelude::*,
+    ui::{Anchor, UiText, UiTransform},
+    utils::application_root_dir,
+};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Name of the `Loader` source of the user music directory.
+const USER_MUSIC_SOURCE: &str = "user_music";
+/// Songs listed at once.
+const VISIBLE_SONGS: usize = 8;
+const LINE_HEIGHT: f32 = 40.0;
+const SELECTED_COLOR: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+const COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Where to look for songs besides `assets/audio`, read from
+/// `config/music.ron`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MusicConfig {
+    #[serde(default)]
+    pub directory: Option<String>,
+}
+
+/// A song that can be played, with what is known about it.
+struct Song {
+    file: MusicFile,
+    title: String,
+    /// Length of the song in seconds, if it could be read.
+    duration: Option<f32>,
+    /// Tempo of the last analysis of the song, if there was one.
+    bpm: Option<f32>,
+}
+
+impl Song {
+    fn new(file: MusicFile) -> Song {
+        let title = Path::new(&file.audio_file)
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().replace('_', " "));
+        let path = file.path().to_string_lossy().into_owned();
+        let summary = beats::cached_summary(&path);
+        Song {
+            file,
+            title,
+            duration: beats::track_duration(&path)
+                .or_else(|| summary.map(|(duration, _)| duration)),
+            bpm: summary.map(|(_, bpm)| bpm),
+        }
+    }
+
+    fn line(&self) -> String {
+        let duration = match self.duration {
+            Some(duration) => {
+                let seconds = duration.round() as u32;
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            }
+            None => "-:--".to_owned(),
+        };
+        let bpm = match self.bpm {
+            Some(bpm) => format!("{:.0}", bpm),
+            None => "?".to_owned(),
+        };
+        format!("{}  {}  {} BPM", self.title, duration, bpm)
+    }
+}
+
+/// Lists the Ogg and WAV files of `assets/audio` and of the user music
+/// directory, and starts a game with the one picked.
+#[derive(Default)]
+pub struct SongSelect {
+    songs: Vec<Song>,
+    selected: usize,
+    lines: Vec<Entity>,
+}
+
+impl SimpleState for SongSelect {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let StateData { world, .. } = data;
+        self.songs = find_songs(world);
+        if self.songs.is_empty() {
+            error!("There are no songs to play");
+        }
+
+        let font = load_font(world);
+        for i in 0..VISIBLE_SONGS.min(self.songs.len()) {
+            let transform = UiTransform::new(
+                format!("song_{}", i),
+                Anchor::TopMiddle,
+                Anchor::TopMiddle,
+                0.,
+                -LINE_HEIGHT * (i as f32 + 1.0),
+                1.,
+                800.,
+                LINE_HEIGHT,
+            );
+            let line = world
+                .create_entity()
+                .with(transform)
+                .with(UiText::new(font.clone(), String::new(), COLOR, 30.))
+                .build();
+            self.lines.push(line);
+        }
+        self.show(world);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        if let Err(e) = data.world.delete_entities(&self.lines) {
+            error!("Could not remove the song list: {}", e);
+        }
+        self.lines.clear();
+    }
+
+    fn handle_event(
+        &mut self,
+        data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        if let StateEvent::Input(InputEvent::ActionPressed(action)) = event {
+            match action.as_str() {
+                "previous" if self.selected > 0 => self.selected -= 1,
+                "next" if self.selected + 1 < self.songs.len() => self.selected += 1,
+                "select" => {
+                    if let Some(song) = self.songs.get(self.selected) {
+                        return Trans::Switch(Box::new(Pong::new(song.file.clone())));
+                    }
+                }
+                "cancel" => return Trans::Switch(Box::new(MainMenu::default())),
+                _ => {}
+            }
+            self.show(data.world);
+        }
+        Trans::None
+    }
+}
+
+impl SongSelect {
+    /// Writes the songs around the selected one into the lines, and
+    /// highlights it.
+    fn show(&self, world: &mut World) {
+        let first = (self.selected + 1).saturating_sub(VISIBLE_SONGS);
+        let mut texts = world.write_storage::<UiText>();
+        for (i, line) in self.lines.iter().enumerate() {
+            if let Some(text) = texts.get_mut(*line) {
+                let index = first + i;
+                text.text = self.songs.get(index).map_or_else(String::new, Song::line);
+                text.color = if index == self.selected {
+                    SELECTED_COLOR
+                } else {
+                    COLOR
+                };
+            }
+        }
+    }
+}
+
+/// The songs of `assets/audio`, and then those of the user music directory,
+/// each sorted by title. The user music directory is added as a `Loader`
+/// source on the way.
+fn find_songs(world: &mut World) -> Vec<Song> {
+    let mut songs = vec![];
+    match application_root_dir() {
+        Ok(root) => {
+            let assets = root.join("assets");
+            let sounds = world.read_resource::<GameConfig>().audio.clone();
+            let mut found = audio_files(&assets, "audio")
+                .into_iter()
+                .filter(|file| {
+                    !AUDIO_EFFECTS.contains(&file.as_str())
+                        && *file != sounds.bounce
+                        && *file != sounds.score
+                })
+                .map(|file| {
+                    Song::new(MusicFile {
+                        audio_file: file,
+                        directory: assets.clone(),
+                        source: None,
+                        beatmap: None,
+                    })
+                })
+                .collect::<Vec<_>>();
+            found.sort_by(|a, b| a.title.cmp(&b.title));
+            songs.extend(found);
+        }
+        Err(e) => error!("Could not find the assets: {}", e),
+    }
+
+    let user_dir = world.read_resource::<MusicConfig>().directory.clone();
+    if let Some(directory) = user_dir {
+        world
+            .write_resource::<Loader>()
+            .add_source(USER_MUSIC_SOURCE, Directory::new(&directory));
+        let mut found = audio_files(Path::new(&directory), "")
+            .into_iter()
+            .map(|file| {
+                Song::new(MusicFile {
+                    audio_file: file,
+                    directory: directory.clone().into(),
+                    source: Some(USER_MUSIC_SOURCE.to_owned()),
+                    beatmap: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.title.cmp(&b.title));
+        songs.extend(found);
+    }
+    songs
+}
+
+/// The Ogg and WAV files in `subdirectory` of `root`, as paths relative to
+/// `root`.
+fn audio_files(root: &Path, subdirectory: &str) -> Vec<String> {
+    let entries = match fs::read_dir(root.join(subdirectory)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!(
+                "Could not list the songs in {:?}: {}",
+                root.join(subdirectory),
+                e
+            );
+            return vec![];
+        }
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().map_or(false, |extension| {
+                extension.eq_ignore_ascii_case("ogg") || extension.eq_ignore_ascii_case("wav")
+            })
+        })
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            Some(if subdirectory.is_empty() {
+                name
+            } else {
+                format!("{}/{}", subdirectory, name)
+            })
+        })
+        .collect()
+}
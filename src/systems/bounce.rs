@@ -1,91 +1,204 @@
-use crate::pong::Intervals;
 use crate::{
-    audio::{play_bounce, Sounds},
+    audio::{play_bounce, PlaybackClock, Sounds},
+    config::GameConfig,
+    physics::{ContactEvent, FixedTimestep, PhysicsPose},
+    score::MatchRules,
+    timeline::BeatTimeline,
     Ball, Paddle, Side,
 };
-use crate::{ARENA_HEIGHT, ARENA_WIDTH, BALL_RADIUS, PADDLE_HEIGHT, PADDLE_WIDTH};
 use amethyst::{
     assets::AssetStorage,
     audio::{output::Output, Source},
     core::transform::Transform,
     derive::SystemDesc,
-    ecs::prelude::{Join, Read, ReadExpect, ReadStorage, System, SystemData, Write, WriteStorage},
+    ecs::prelude::{Entities, Join, Read, ReadStorage, System, SystemData, Write, WriteStorage},
+    shrev::EventChannel,
 };
-use std::ops::Deref;
+use std::{cmp::Ordering, f32::INFINITY, ops::Deref};
+
+/// Most bounces a ball makes in one physics step, e.g. in a corner.
+const MAX_CONTACTS_PER_STEP: usize = 4;
+/// Shortest way a routed ball is sent, as a fraction of the arena.
+const MIN_ROUTE: f32 = 0.25;
 
 /// This system is responsible for detecting collisions between balls and
 /// paddles, as well as balls and the top and bottom edges of the arena.
+///
+/// The collisions are swept: the ball is followed from where the last step
+/// left it to where `MoveBallsSystem` put it, and bounces at the exact time
+/// it touches a paddle, carrying on with the rest of its motion. However fast
+/// the ball goes, it can't pass through a paddle. Every contact is sent as a
+/// `ContactEvent`.
+///
+/// When the music has beats, every bounce also sets the speed of the ball so
+/// that its next contact lands on a beat, and with `route_downbeats` in the
+/// rules, its direction so that the contact is on the paddles of that beat.
 #[derive(SystemDesc)]
 pub struct BounceSystem;
 
 impl<'s> System<'s> for BounceSystem {
     type SystemData = (
+        Entities<'s>,
         WriteStorage<'s, Ball>,
         ReadStorage<'s, Paddle>,
-        ReadStorage<'s, Transform>,
+        ReadStorage<'s, PhysicsPose>,
+        WriteStorage<'s, Transform>,
         Read<'s, AssetStorage<Source>>,
-        ReadExpect<'s, Sounds>,
+        Option<Read<'s, Sounds>>,
         Option<Read<'s, Output>>,
-        Write<'s, Intervals>,
+        Read<'s, BeatTimeline>,
+        Read<'s, PlaybackClock>,
+        Read<'s, FixedTimestep>,
+        Read<'s, GameConfig>,
+        Read<'s, MatchRules>,
+        Write<'s, EventChannel<ContactEvent>>,
     );
 
     fn run(
         &mut self,
-        (mut balls, paddles, transforms, storage, sounds, audio_output, mut intervals): Self::SystemData,
+        (
+            entities,
+            mut balls,
+            paddles,
+            poses,
+            mut transforms,
+            storage,
+            sounds,
+            audio_output,
+            timeline,
+            clock,
+            timestep,
+            config,
+            rules,
+            mut contacts,
+        ): Self::SystemData,
     ) {
-        // Check whether a ball collided, and bounce off accordingly.
-        //
-        // We also check for the velocity of the ball every time, to prevent multiple collisions
-        // from occurring.
-        for (ball, transform) in (&mut balls, &transforms).join() {
-            let magic_time = 0.6; //intervals.next().unwrap_or(0.0);
-                                  //println!("magic_time:{}", magic_time);
-            let ball_x = transform.translation().x;
-            let ball_y = transform.translation().y;
-            // Bounce at the paddles.
-            for (paddle, paddle_transform) in (&paddles, &transforms).join() {
+        // The beats of the timeline are already as late as the speakers. The
+        // clock only moves once a frame, the steps of a frame each have
+        // their own time.
+        let now = timestep.time;
+        let track = clock.track.unwrap_or(0);
+        // The paddles are gathered first, as the transforms of the balls are
+        // written below.
+        let paddles = (&paddles, &transforms)
+            .join()
+            .map(|(paddle, paddle_transform)| {
                 let paddle_x = paddle_transform.translation().x - (paddle.width * 0.5);
                 let paddle_y = paddle_transform.translation().y - (paddle.height * 0.5);
+                (paddle.side, paddle_x, paddle_y, paddle.width, paddle.height)
+            })
+            .collect::<Vec<_>>();
 
-                // To determine whether the ball has collided with a paddle, we create a larger
+        for (entity, ball, transform, pose) in
+            (&entities, &mut balls, &mut transforms, poses.maybe()).join()
+        {
+            let mut end = [transform.translation().x, transform.translation().y];
+            let mut start = pose.map_or(end, |pose| pose.current);
+            // How much of the step the ball already went through.
+            let mut elapsed = 0.0;
+            for _ in 0..MAX_CONTACTS_PER_STEP {
+                // To determine whether the ball collides with a paddle, we create a larger
                 // rectangle around the current one, by subtracting the ball radius from the
                 // lowest coordinates, and adding the ball radius to the highest ones. The ball
-                // is then within the paddle if its centre is within the larger wrapper
-                // rectangle.
-                let rectangle = HitRectangle::new(paddle_x, paddle_y, ball.radius, &paddle.side);
-                if point_in_rect(ball_x, ball_y, &rectangle) {
-                    if (paddle.side == Side::Left && ball.velocity[0] < 0.0)
-                        || (paddle.side == Side::Right && ball.velocity[0] > 0.0)
-                    {
+                // touches the paddle when its centre enters the larger wrapper rectangle.
+                //
+                // We also check for the velocity of the ball, to only bounce off paddles the
+                // ball is heading into.
+                let hit = paddles
+                    .iter()
+                    .filter(|(side, _, _, _, _)| heading_into(side, &ball.velocity))
+                    .filter_map(|(side, paddle_x, paddle_y, width, height)| {
+                        let rectangle =
+                            HitRectangle::new(*paddle_x, *paddle_y, *width, *height, ball.radius);
+                        time_of_impact(start, end, &rectangle, radius_offset(ball.radius))
+                            .filter(|t| t.is_finite())
+                            .map(|t| (t, *side))
+                    })
+                    .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                let (t, side) = match hit {
+                    Some(hit) => hit,
+                    None => break,
+                };
+
+                let centre = [
+                    start[0] + (end[0] - start[0]) * t,
+                    start[1] + (end[1] - start[1]) * t,
+                ];
+                elapsed += (1.0 - elapsed) * t;
+                let time = now + elapsed * timestep.step;
+                let point = match side {
+                    Side::Left | Side::Right => {
+                        let point = [
+                            centre[0] + ball.radius * ball.velocity[0].signum(),
+                            centre[1],
+                        ];
                         ball.velocity[0] = -ball.velocity[0];
-                        //ball.velocity =
-                        //    adjust_velocity(&ball_x, &ball_y, &ball.velocity, &magic_time);
-                        play_bounce(&*sounds, &storage, audio_output.as_ref().map(|o| o.deref()));
-                    } else if (paddle.side == Side::Top && ball.velocity[1] < 0.0)
-                        || (paddle.side == Side::Bottom && ball.velocity[1] > 0.0)
-                    {
+                        point
+                    }
+                    Side::Top | Side::Bottom => {
+                        let point = [
+                            centre[0],
+                            centre[1] + ball.radius * ball.velocity[1].signum(),
+                        ];
                         ball.velocity[1] = -ball.velocity[1];
-                        //ball.velocity =
-                        //    adjust_velocity(&ball_x, &ball_y, &ball.velocity, &magic_time);
-                        play_bounce(&*sounds, &storage, audio_output.as_ref().map(|o| o.deref()));
+                        point
                     }
-                }
+                };
+                ball.velocity = sync_to_beat(
+                    centre[0],
+                    centre[1],
+                    ball,
+                    (&timeline, track),
+                    time,
+                    &config,
+                    rules.route_downbeats,
+                );
+                play_bounce(
+                    sounds.as_ref().map(|s| s.deref()),
+                    &storage,
+                    audio_output.as_ref().map(|o| o.deref()),
+                );
+                contacts.single_write(ContactEvent {
+                    ball: entity,
+                    side,
+                    point,
+                    time,
+                    track,
+                });
+
+                // The rest of the step is spent going the new way.
+                let remaining = (1.0 - elapsed) * timestep.step;
+                start = centre;
+                end = [
+                    centre[0] + ball.velocity[0] * remaining,
+                    centre[1] + ball.velocity[1] * remaining,
+                ];
             }
+            transform.set_translation_x(end[0]);
+            transform.set_translation_y(end[1]);
         }
     }
 }
+
+/// Whether a ball going at `velocity` moves towards the paddle on `side`.
+fn heading_into(side: &Side, velocity: &[f32; 2]) -> bool {
+    match side {
+        Side::Left => velocity[0] < 0.0,
+        Side::Right => velocity[0] > 0.0,
+        Side::Top => velocity[1] < 0.0,
+        Side::Bottom => velocity[1] > 0.0,
+    }
+}
+
 const OFF_SET: f32 = 1.1;
 fn radius_offset(ball_radius: f32) -> f32 {
     ball_radius * OFF_SET
 }
 pub struct Top(pub f32);
 impl Top {
-    pub fn new(paddle_y: f32, ball_radius: f32, side: &Side) -> Top {
+    pub fn new(paddle_y: f32, paddle_height: f32, ball_radius: f32) -> Top {
         let ball_radius_offset = radius_offset(ball_radius);
-        match side {
-            Side::Top | Side::Bottom => Top(paddle_y + (PADDLE_WIDTH + ball_radius_offset)),
-            Side::Left | Side::Right => Top(paddle_y + (PADDLE_HEIGHT + ball_radius_offset)),
-        }
+        Top(paddle_y + (paddle_height + ball_radius_offset))
     }
 }
 pub struct Bottom(pub f32);
@@ -104,12 +217,9 @@ impl Left {
 }
 pub struct Right(pub f32);
 impl Right {
-    pub fn new(paddle_x: f32, ball_radius: f32, side: &Side) -> Right {
+    pub fn new(paddle_x: f32, paddle_width: f32, ball_radius: f32) -> Right {
         let ball_radius_offset = radius_offset(ball_radius);
-        match side {
-            Side::Top | Side::Bottom => Right(paddle_x + (PADDLE_HEIGHT + ball_radius_offset)),
-            Side::Left | Side::Right => Right(paddle_x + (PADDLE_WIDTH + ball_radius_offset)),
-        }
+        Right(paddle_x + (paddle_width + ball_radius_offset))
     }
 }
 pub struct HitRectangle {
@@ -119,45 +229,173 @@ pub struct HitRectangle {
     pub right: Right,
 }
 impl HitRectangle {
-    pub fn new(paddle_x: f32, paddle_y: f32, ball_radius: f32, side: &Side) -> HitRectangle {
+    pub fn new(
+        paddle_x: f32,
+        paddle_y: f32,
+        paddle_width: f32,
+        paddle_height: f32,
+        ball_radius: f32,
+    ) -> HitRectangle {
         HitRectangle {
-            top: Top::new(paddle_y, ball_radius, side),
+            top: Top::new(paddle_y, paddle_height, ball_radius),
             bottom: Bottom::new(paddle_y, ball_radius),
             left: Left::new(paddle_x, ball_radius),
-            right: Right::new(paddle_x, ball_radius, side),
+            right: Right::new(paddle_x, paddle_width, ball_radius),
         }
     }
 }
 
-fn adjust_velocity(x: &f32, y: &f32, velocity: &[f32; 2], magic_time: &f32) -> [f32; 2] {
-    match fixed_coordinate(x, y, velocity) {
-        (xm, ym) => [(xm - x) / magic_time, (ym - y) / magic_time],
+/// Solves the velocity that makes the next contact of the ball, keeping its
+/// direction, happen exactly on a beat of the track playing, or of the one
+/// after it when the contact would come after the end. Beats that would need
+/// a ball faster than the configured `max_velocity` are skipped, and once the
+/// beats run out the ball keeps its speed.
+///
+/// With `route_downbeats`, the ball is turned towards the left or right
+/// paddle when that beat starts a bar, and towards the top or bottom one
+/// otherwise. The longer or shorter way there may land it on the beat after,
+/// whichever kind that is.
+fn sync_to_beat(
+    x: f32,
+    y: f32,
+    ball: &Ball,
+    (timeline, track): (&BeatTimeline, usize),
+    now: f32,
+    config: &GameConfig,
+    route_downbeats: bool,
+) -> [f32; 2] {
+    let contact = match fixed_coordinate(x, y, &ball.velocity, ball.radius, config) {
+        Some(contact) => contact,
+        None => return ball.velocity,
+    };
+    let distance_to = |(xm, ym): (f32, f32)| ((xm - x).powi(2) + (ym - y).powi(2)).sqrt();
+    if distance_to(contact) <= std::f32::EPSILON {
+        return ball.velocity;
+    }
+    let mut beat = timeline.next_beat(track, now + distance_to(contact) / config.ball.max_velocity);
+    let mut contact = contact;
+    if let (true, Some(next)) = (route_downbeats, beat) {
+        let sideways = timeline.is_downbeat(track, next);
+        if let Some(routed) = aim(x, y, &ball.velocity, ball.radius, config, sideways) {
+            contact = routed;
+            beat = timeline.next_beat(track, now + distance_to(contact) / config.ball.max_velocity);
+        }
+    }
+    match beat {
+        Some(beat) => adjust_velocity(x, y, contact, beat - now),
+        None => ball.velocity,
     }
 }
 
-fn fixed_coordinate(x: &f32, y: &f32, velocity: &[f32; 2]) -> (f32, f32) {
+fn adjust_velocity(x: f32, y: f32, (xm, ym): (f32, f32), time: f32) -> [f32; 2] {
+    [(xm - x) / time, (ym - y) / time]
+}
+
+/// Where the ball, going straight, next reaches the line of one of the
+/// paddles. When it heads into a corner, that is whichever line comes first.
+/// `None` when the ball is not heading towards any of them, e.g. because it
+/// is already past a paddle.
+fn fixed_coordinate(
+    x: f32,
+    y: f32,
+    velocity: &[f32; 2],
+    radius: f32,
+    config: &GameConfig,
+) -> Option<(f32, f32)> {
+    // The paddles are as thick as they are wide on the left and right.
+    let (arena, thickness) = (&config.arena, config.paddle.width);
     let xm = if velocity[0] > 0.0 {
-        let Left(left) = Left::new(ARENA_WIDTH - PADDLE_WIDTH, BALL_RADIUS);
+        let Left(left) = Left::new(arena.width - thickness, radius);
         left
     } else {
-        let Right(right) = Right::new(PADDLE_WIDTH, BALL_RADIUS, &Side::Left);
+        let Right(right) = Right::new(0.0, thickness, radius);
         right
     };
     let ym = if velocity[1] > 0.0 {
-        let Bottom(bottom) = Bottom::new(ARENA_HEIGHT - PADDLE_WIDTH, BALL_RADIUS);
+        let Bottom(bottom) = Bottom::new(arena.height - thickness, radius);
         bottom
     } else {
-        let Top(top) = Top::new(PADDLE_WIDTH, BALL_RADIUS, &Side::Bottom);
+        let Top(top) = Top::new(0.0, thickness, radius);
         top
     };
-    let tx = (xm - *x) / velocity[0];
-    let ty = (ym - *y) / velocity[1];
-    if tx <= ty {
-        let ym = velocity[1] * tx + y;
-        (xm, ym)
+    let t = [time_to(x, xm, velocity[0]), time_to(y, ym, velocity[1])]
+        .iter()
+        .cloned()
+        .filter(|t| *t > 0.0)
+        .fold(INFINITY, f32::min);
+    if t.is_finite() {
+        Some((x + velocity[0] * t, y + velocity[1] * t))
     } else {
-        let xm = velocity[0] * ty + x;
-        (xm, ym)
+        None
+    }
+}
+
+/// Where to send the ball to reach the line of the left or right paddles
+/// when `sideways`, or else of the top or bottom ones. It keeps going the
+/// same way along that axis, unless the line ahead is so close that it would
+/// crawl there, and then it turns to the line across. Along the other axis
+/// it drifts as it did, but is kept a paddle length away from the corners,
+/// so that no other line comes first and the paddle can get there.
+fn aim(
+    x: f32,
+    y: f32,
+    velocity: &[f32; 2],
+    radius: f32,
+    config: &GameConfig,
+    sideways: bool,
+) -> Option<(f32, f32)> {
+    let (arena, thickness) = (&config.arena, config.paddle.width);
+    let (Right(x_low), Left(x_high)) = (
+        Right::new(0.0, thickness, radius),
+        Left::new(arena.width - thickness, radius),
+    );
+    let (Top(y_low), Bottom(y_high)) = (
+        Top::new(0.0, thickness, radius),
+        Bottom::new(arena.height - thickness, radius),
+    );
+    // The axis the ball is sent along, and the one it drifts along.
+    let ((along, speed, low, high), (across, drift, across_low, across_high)) = if sideways {
+        (
+            (x, velocity[0], x_low, x_high),
+            (y, velocity[1], y_low, y_high),
+        )
+    } else {
+        (
+            (y, velocity[1], y_low, y_high),
+            (x, velocity[0], x_low, x_high),
+        )
+    };
+    // Out of the corners, where the paddle of the line can't reach.
+    let margin = config.paddle.height;
+    if speed == 0.0 || high <= low || across_high - across_low <= 2.0 * margin {
+        return None;
+    }
+    let (ahead, behind) = if speed > 0.0 {
+        (high, low)
+    } else {
+        (low, high)
+    };
+    let line = if (ahead - along).abs() >= MIN_ROUTE * (high - low) {
+        ahead
+    } else {
+        behind
+    };
+    let t = (line - along).abs() / speed.abs();
+    let reached = (across + drift * t)
+        .max(across_low + margin)
+        .min(across_high - margin);
+    Some(if sideways {
+        (line, reached)
+    } else {
+        (reached, line)
+    })
+}
+
+fn time_to(from: f32, to: f32, velocity: f32) -> f32 {
+    if velocity == 0.0 {
+        INFINITY
+    } else {
+        (to - from) / velocity
     }
 }
 
@@ -173,3 +411,138 @@ fn point_in_rect(x: f32, y: f32, rectangle: &HitRectangle) -> bool {
         } => x >= *left && x <= *right && y >= *bottom && y <= *top,
     }
 }
+
+/// When the centre of a ball going from `start` to `end` enters the hit
+/// rectangle, as a fraction of the way, or `None` if it doesn't. The corners
+/// of the rectangle are rounded with `corner_radius`, as a circle touches a
+/// corner of the paddle from further away diagonally. A ball that starts
+/// inside is hit straight away.
+fn time_of_impact(
+    start: [f32; 2],
+    end: [f32; 2],
+    rectangle: &HitRectangle,
+    corner_radius: f32,
+) -> Option<f32> {
+    if point_in_rect(start[0], start[1], rectangle) {
+        return Some(0.0);
+    }
+    let (top, bottom, left, right) = (
+        rectangle.top.0,
+        rectangle.bottom.0,
+        rectangle.left.0,
+        rectangle.right.0,
+    );
+    let (x_enter, x_exit) = slab(start[0], end[0], left, right)?;
+    let (y_enter, y_exit) = slab(start[1], end[1], bottom, top)?;
+    let enter = x_enter.max(y_enter);
+    if enter > x_exit.min(y_exit) || enter > 1.0 || enter < 0.0 {
+        return None;
+    }
+
+    let x = start[0] + (end[0] - start[0]) * enter;
+    let y = start[1] + (end[1] - start[1]) * enter;
+    let corner_x = x.max(left + corner_radius).min(right - corner_radius);
+    let corner_y = y.max(bottom + corner_radius).min(top - corner_radius);
+    if x == corner_x || y == corner_y {
+        return Some(enter);
+    }
+    // The ball enters the rectangle by one of its corners, which only counts
+    // when it also touches the rounded corner.
+    let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+    let (fx, fy) = (start[0] - corner_x, start[1] - corner_y);
+    let a = dx * dx + dy * dy;
+    let b = 2.0 * (dx * fx + dy * fy);
+    let c = fx * fx + fy * fy - corner_radius * corner_radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / (2.0 * a);
+    if t >= 0.0 && t <= 1.0 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// The fractions of the way from `from` to `to` between which a coordinate is
+/// within `low` and `high`, or `None` if it never is.
+fn slab(from: f32, to: f32, low: f32, high: f32) -> Option<(f32, f32)> {
+    let delta = to - from;
+    if delta == 0.0 {
+        if from >= low && from <= high {
+            Some((-INFINITY, INFINITY))
+        } else {
+            None
+        }
+    } else {
+        let (a, b) = ((low - from) / delta, (high - from) / delta);
+        Some((a.min(b), a.max(b)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rectangle() -> HitRectangle {
+        HitRectangle {
+            top: Top(10.0),
+            bottom: Bottom(0.0),
+            left: Left(10.0),
+            right: Right(20.0),
+        }
+    }
+
+    #[test]
+    fn slab_is_where_the_coordinate_is_between_the_bounds() {
+        assert_eq!(slab(0.0, 10.0, 2.0, 4.0), Some((0.2, 0.4)));
+        assert_eq!(slab(10.0, 0.0, 2.0, 4.0), Some((0.6, 0.8)));
+        assert_eq!(slab(3.0, 3.0, 2.0, 4.0), Some((-INFINITY, INFINITY)));
+        assert_eq!(slab(5.0, 5.0, 2.0, 4.0), None);
+    }
+
+    #[test]
+    fn time_of_impact_on_a_side() {
+        let rectangle = rectangle();
+        assert_eq!(
+            time_of_impact([0.0, 5.0], [20.0, 5.0], &rectangle, 1.0),
+            Some(0.5)
+        );
+        assert_eq!(
+            time_of_impact([15.0, 30.0], [15.0, -10.0], &rectangle, 1.0),
+            Some(0.5)
+        );
+        assert_eq!(
+            time_of_impact([0.0, 20.0], [20.0, 20.0], &rectangle, 1.0),
+            None
+        );
+        // Too short to get there.
+        assert_eq!(
+            time_of_impact([0.0, 5.0], [5.0, 5.0], &rectangle, 1.0),
+            None
+        );
+    }
+
+    #[test]
+    fn time_of_impact_from_inside_is_straight_away() {
+        assert_eq!(
+            time_of_impact([15.0, 5.0], [30.0, 5.0], &rectangle(), 1.0),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn time_of_impact_on_a_rounded_corner() {
+        let rectangle = rectangle();
+        // Towards the corner, the ball touches the rounding a little later
+        // than it would the square corner.
+        let t = time_of_impact([0.0, 20.0], [20.0, 0.0], &rectangle, 1.0).unwrap();
+        assert!(t > 0.5 && t < 0.52, "{}", t);
+        // It clips the square corner, but misses the rounding.
+        assert_eq!(
+            time_of_impact([0.0, -0.15], [20.0, 19.85], &rectangle, 1.0),
+            None
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use crate::audio::Music;
+use amethyst::{
+    assets::AssetStorage,
+    audio::{AudioSink, Source},
+    ecs::prelude::{Read, System, Write},
+};
+use log::error;
+
+/// This system plays the tracks of the `Music` one after the other, like
+/// amethyst's `DjSystem`, except that a track is only handed to the sink, and
+/// counted as started, once it is loaded. The `PlaybackClock` restarts on
+/// every start, so a track still loading must not count as one.
+#[derive(Default)]
+pub struct DjSystem;
+
+impl<'s> System<'s> for DjSystem {
+    type SystemData = (
+        Read<'s, AssetStorage<Source>>,
+        Option<Read<'s, AudioSink>>,
+        Option<Write<'s, Music>>,
+    );
+
+    fn run(&mut self, (storage, sink, music): Self::SystemData) {
+        let (sink, mut music) = match (sink, music) {
+            (Some(sink), Some(music)) => (sink, music),
+            _ => return,
+        };
+        if !sink.empty() {
+            return;
+        }
+        let source = match music.upcoming().and_then(|track| storage.get(track)) {
+            Some(source) => source,
+            None => return,
+        };
+        match sink.append(source) {
+            Ok(()) => music.started += 1,
+            Err(e) => error!("Could not play the next track: {}", e),
+        }
+    }
+}
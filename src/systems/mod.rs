@@ -1,13 +1,21 @@
 mod bounce;
+mod dj;
 mod move_balls;
 mod paddle;
+mod playback_clock;
+mod pose;
+mod rhythm;
 mod winner;
 mod move_paddle;
 
 pub use self::{
     bounce::{BounceSystem, Top, Bottom, Left, Right},
+    dj::DjSystem,
     move_balls::MoveBallsSystem,
     paddle::PaddleSystem,
+    playback_clock::PlaybackClockSystem,
+    pose::{InterpolatePoseSystem, RestorePoseSystem, StorePoseSystem},
+    rhythm::RhythmSystem,
     move_paddle::MovePaddleSystem,
     winner::WinnerSystem,
 };
@@ -1,6 +1,6 @@
-use crate::{Paddle, Side};
+use crate::{config::GameConfig, physics::FixedTimestep, Paddle, Side};
 use amethyst::{
-    core::{timing::Time, transform::Transform},
+    core::transform::Transform,
     derive::SystemDesc,
     ecs::prelude::{Join, Read, System, SystemData, WriteStorage},
 };
@@ -12,31 +12,32 @@ impl<'s> System<'s> for MovePaddleSystem {
     type SystemData = (
         WriteStorage<'s, Paddle>,
         WriteStorage<'s, Transform>,
-        Read<'s, Time>,
+        Read<'s, FixedTimestep>,
+        Read<'s, GameConfig>,
     );
 
-    fn run(&mut self, (mut paddle, mut transforms, time): Self::SystemData) {
-        use crate::{ARENA_HEIGHT, ARENA_WIDTH};
+    fn run(&mut self, (mut paddle, mut transforms, timestep, config): Self::SystemData) {
+        let arena = &config.arena;
         for (paddle, paddle_transform) in (&mut paddle, &mut transforms).join() {
             match paddle.side {
                 Side::Left | Side::Right => {
-                    paddle_transform.prepend_translation_y(paddle.velocity * time.delta_seconds());
+                    paddle_transform.prepend_translation_y(paddle.velocity * timestep.step);
                     // We make sure the paddle remains in the arena.
                     let paddle_y = paddle_transform.translation().y;
                     paddle_transform.set_translation_y(
                         paddle_y
                             .max(paddle.height * 0.5)
-                            .min(ARENA_HEIGHT - paddle.height * 0.5),
+                            .min(arena.height - paddle.height * 0.5),
                     );
                 }
                 Side::Top | Side::Bottom => {
-                    paddle_transform.prepend_translation_x(paddle.velocity * time.delta_seconds());
+                    paddle_transform.prepend_translation_x(paddle.velocity * timestep.step);
                     // We make sure the paddle remains in the arena.
                     let paddle_x = paddle_transform.translation().x;
                     paddle_transform.set_translation_x(
                         paddle_x
                             .max(paddle.width * 0.5)
-                            .min(ARENA_WIDTH - paddle.width * 0.5),
+                            .min(arena.width - paddle.width * 0.5),
                     );
                 }
             }
@@ -1,46 +1,97 @@
 use crate::systems::{Bottom, Left, Right, Top};
 use crate::Side;
-use crate::{Ball, Paddle};
+use crate::{config::GameConfig, controller::Controller, Ball, Paddle};
 use amethyst::{
     core::transform::Transform,
     derive::SystemDesc,
-    ecs::prelude::{Join, ReadStorage, System, SystemData, WriteStorage},
+    ecs::prelude::{Join, Read, ReadStorage, System, SystemData, WriteStorage},
+    input::{InputHandler, StringBindings},
 };
+use std::cmp::Ordering;
 
-/// This system is responsible for moving all the paddles according to the user
-/// provided input.
+/// How quickly the AI closes in on where it expects the ball, per second.
+const AI_REACTION: f32 = 4.0;
+
+/// This system is responsible for setting the speed of every paddle, as its
+/// controller decides: players move their paddles with the axes of the input
+/// bindings, and the computer moves the rest towards the ball.
 #[derive(SystemDesc)]
 pub struct PaddleSystem;
 
 impl<'s> System<'s> for PaddleSystem {
     type SystemData = (
         WriteStorage<'s, Paddle>,
-        WriteStorage<'s, Transform>,
+        ReadStorage<'s, Transform>,
         ReadStorage<'s, Ball>,
+        Option<Read<'s, InputHandler<StringBindings>>>,
+        Read<'s, GameConfig>,
     );
 
-    fn run(&mut self, (mut paddle, transforms, ball): Self::SystemData) {
-        for (ball, ball_transform) in (&ball, &transforms).join() {
-            let ball_x = ball_transform.translation().x;
-            let ball_y = ball_transform.translation().y;
-            for (paddle, paddle_transform) in (&mut paddle, &transforms).join() {
-                let paddle_x = paddle_transform.translation().x;
-                let paddle_y = paddle_transform.translation().y;
-                let collision = CollisionPrediction::new(
+    fn run(&mut self, (mut paddles, transforms, balls, input, config): Self::SystemData) {
+        for (paddle, paddle_transform) in (&mut paddles, &transforms).join() {
+            let paddle_x = paddle_transform.translation().x;
+            let paddle_y = paddle_transform.translation().y;
+            let (width, height) = (paddle.width, paddle.height);
+            let predictions = (&balls, &transforms).join().map(|(ball, ball_transform)| {
+                CollisionPrediction::new(
                     paddle_x,
                     paddle_y,
-                    ball_x,
-                    ball_y,
+                    ball_transform.translation().x,
+                    ball_transform.translation().y,
+                    width,
+                    height,
                     ball,
                     &paddle.side,
-                );
-                let movement = collision.intersection_velocity;
-                paddle.velocity = movement;
-            }
+                )
+            });
+            paddle.velocity = match &paddle.controller {
+                Controller::Human(axis) => {
+                    let value = input.as_ref().and_then(|input| input.axis_value(axis));
+                    value.unwrap_or(0.0) * config.paddle.speed
+                }
+                Controller::Ai => {
+                    let (position, length) = match paddle.side {
+                        Side::Left | Side::Right => (paddle_y, config.arena.height),
+                        Side::Top | Side::Bottom => (paddle_x, config.arena.width),
+                    };
+                    // Go for the ball that arrives first, and wait in the middle when none is
+                    // coming.
+                    let target = predictions
+                        .filter(|prediction| prediction.time_until_collision > 0.0)
+                        .min_by(|a, b| {
+                            a.time_until_collision
+                                .partial_cmp(&b.time_until_collision)
+                                .unwrap_or(Ordering::Equal)
+                        })
+                        .map(|prediction| match paddle.side {
+                            Side::Left | Side::Right => prediction.y,
+                            Side::Top | Side::Bottom => prediction.x,
+                        })
+                        .filter(|target| target.is_finite())
+                        .unwrap_or(length / 2.0);
+                    ai_velocity(
+                        position,
+                        target.max(0.0).min(length),
+                        config.paddle.ai_speed,
+                    )
+                }
+                Controller::BeatAutopilot => {
+                    predictions.last().map_or(paddle.velocity, |prediction| {
+                        prediction.intersection_velocity
+                    })
+                }
+            };
         }
     }
 }
 
+/// Heads for `target`, slowing down on the way, no faster than `max_speed`.
+fn ai_velocity(position: f32, target: f32, max_speed: f32) -> f32 {
+    ((target - position) * AI_REACTION)
+        .max(-max_speed)
+        .min(max_speed)
+}
+
 pub struct CollisionPrediction {
     pub x: f32,
     pub y: f32,
@@ -54,14 +105,16 @@ impl CollisionPrediction {
         paddle_y: f32,
         ball_x: f32,
         ball_y: f32,
+        paddle_width: f32,
+        paddle_height: f32,
         ball: &Ball,
         destination: &Side,
     ) -> CollisionPrediction {
         let [velocity_x, velocity_y] = ball.velocity;
 
         let Left(left) = Left::new(paddle_x, ball.radius);
-        let Right(right) = Right::new(paddle_x, ball.radius, destination);
-        let Top(top) = Top::new(paddle_y, ball.radius, destination);
+        let Right(right) = Right::new(paddle_x, paddle_width, ball.radius);
+        let Top(top) = Top::new(paddle_y, paddle_height, ball.radius);
         let Bottom(bottom) = Bottom::new(paddle_y, ball.radius);
 
         let end_x = if velocity_x < 0.0 { left } else { right };
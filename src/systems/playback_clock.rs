@@ -0,0 +1,83 @@
+use crate::audio::{Music, PlaybackClock};
+use amethyst::{
+    audio::AudioSink,
+    core::timing::Time,
+    ecs::prelude::{Read, System, Write},
+};
+use std::time::Instant;
+
+/// This system keeps the `PlaybackClock` in step with the DJ: the position
+/// restarts whenever the DJ starts a track, including when the playlist
+/// loops, and stands still while the audio sink is paused. It is measured
+/// from when the track was handed to the sink rather than summed from the
+/// frame times, so it doesn't drift away from the sink over a long track.
+#[derive(Default)]
+pub struct PlaybackClockSystem {
+    /// Id of the playlist and number of tracks it started, as last seen.
+    playlist: u64,
+    started: u64,
+    /// When the sink started the playing track, pushed back by every pause.
+    track_start: Option<Instant>,
+    /// When the sink was paused, while it is.
+    paused_since: Option<Instant>,
+    game_position: f32,
+}
+
+impl<'s> System<'s> for PlaybackClockSystem {
+    type SystemData = (
+        Write<'s, PlaybackClock>,
+        Option<Read<'s, Music>>,
+        Option<Read<'s, AudioSink>>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (mut clock, music, sink, time): Self::SystemData) {
+        let now = Instant::now();
+        clock.paused = sink.map_or(false, |sink| sink.is_paused());
+
+        let (playlist, started) = music
+            .as_ref()
+            .map_or((0, 0), |music| (music.id, music.started));
+        if playlist != self.playlist {
+            // The music was replaced by another playlist, which the DJ may
+            // have started already.
+            self.playlist = playlist;
+            self.started = 0;
+            self.track_start = None;
+            self.paused_since = None;
+            self.game_position = 0.0;
+            clock.track = None;
+            clock.loops = 0;
+            clock.position = 0.0;
+        }
+        if started != self.started {
+            let tracks = music.map_or(1, |music| music.tracks().max(1)) as u64;
+            self.started = started;
+            self.track_start = Some(now);
+            self.paused_since = if clock.paused { Some(now) } else { None };
+            self.game_position = 0.0;
+            clock.track = Some(((started - 1) % tracks) as usize);
+            clock.loops = (started - 1) / tracks;
+            clock.position = 0.0;
+        } else if let Some(track_start) = self.track_start {
+            match (clock.paused, self.paused_since) {
+                (true, None) => self.paused_since = Some(now),
+                (false, Some(paused_since)) => {
+                    self.track_start = Some(track_start + (now - paused_since));
+                    self.paused_since = None;
+                }
+                _ => {}
+            }
+            let played_until = self.paused_since.unwrap_or(now);
+            if let Some(track_start) = self.track_start {
+                clock.position = played_until
+                    .saturating_duration_since(track_start)
+                    .as_secs_f32();
+            }
+            if !clock.paused {
+                self.game_position += time.delta_seconds();
+            }
+        }
+        clock.drift = self.game_position - clock.position;
+    }
+}
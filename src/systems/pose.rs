@@ -0,0 +1,61 @@
+use crate::physics::{FixedTimestep, PhysicsPose};
+use amethyst::{
+    core::transform::Transform,
+    derive::SystemDesc,
+    ecs::prelude::{Join, Read, ReadStorage, System, SystemData, WriteStorage},
+};
+
+/// This system runs first in every physics step, and moves the entities back
+/// to where the last step left them, undoing the interpolation.
+#[derive(SystemDesc)]
+pub struct RestorePoseSystem;
+
+impl<'s> System<'s> for RestorePoseSystem {
+    type SystemData = (ReadStorage<'s, PhysicsPose>, WriteStorage<'s, Transform>);
+
+    fn run(&mut self, (poses, mut transforms): Self::SystemData) {
+        for (pose, transform) in (&poses, &mut transforms).join() {
+            transform.set_translation_x(pose.current[0]);
+            transform.set_translation_y(pose.current[1]);
+        }
+    }
+}
+
+/// This system runs last in every physics step, and records where the
+/// entities ended up.
+#[derive(SystemDesc)]
+pub struct StorePoseSystem;
+
+impl<'s> System<'s> for StorePoseSystem {
+    type SystemData = (WriteStorage<'s, PhysicsPose>, ReadStorage<'s, Transform>);
+
+    fn run(&mut self, (mut poses, transforms): Self::SystemData) {
+        for (pose, transform) in (&mut poses, &transforms).join() {
+            pose.previous = pose.current;
+            pose.current = [transform.translation().x, transform.translation().y];
+        }
+    }
+}
+
+/// This system runs every frame, after the physics, and places the entities
+/// between their last two physics poses for rendering.
+#[derive(SystemDesc)]
+pub struct InterpolatePoseSystem;
+
+impl<'s> System<'s> for InterpolatePoseSystem {
+    type SystemData = (
+        ReadStorage<'s, PhysicsPose>,
+        WriteStorage<'s, Transform>,
+        Read<'s, FixedTimestep>,
+    );
+
+    fn run(&mut self, (poses, mut transforms, timestep): Self::SystemData) {
+        let alpha = timestep.alpha();
+        for (pose, transform) in (&poses, &mut transforms).join() {
+            let [previous_x, previous_y] = pose.previous;
+            let [current_x, current_y] = pose.current;
+            transform.set_translation_x(previous_x + (current_x - previous_x) * alpha);
+            transform.set_translation_y(previous_y + (current_y - previous_y) * alpha);
+        }
+    }
+}
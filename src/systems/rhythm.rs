@@ -0,0 +1,86 @@
+use crate::{
+    physics::ContactEvent,
+    rhythm::{Judgement, RhythmScore, RhythmText},
+    score::{MatchRules, PointEvent},
+    timeline::BeatTimeline,
+};
+use amethyst::{
+    ecs::prelude::{Read, System, SystemData, World, Write, WriteStorage},
+    shrev::{EventChannel, ReaderId},
+    ui::UiText,
+};
+
+/// This system grades every contact of a ball with a paddle by how close it
+/// was to the nearest beat, and counts every miss of a paddle as a miss,
+/// when the `MatchRules` have rhythm scoring on.
+#[derive(Default)]
+pub struct RhythmSystem {
+    contacts: Option<ReaderId<ContactEvent>>,
+    points: Option<ReaderId<PointEvent>>,
+}
+
+impl<'s> System<'s> for RhythmSystem {
+    type SystemData = (
+        Read<'s, EventChannel<ContactEvent>>,
+        Read<'s, EventChannel<PointEvent>>,
+        Read<'s, BeatTimeline>,
+        Read<'s, MatchRules>,
+        Write<'s, RhythmScore>,
+        Option<Read<'s, RhythmText>>,
+        WriteStorage<'s, UiText>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.contacts = Some(
+            world
+                .fetch_mut::<EventChannel<ContactEvent>>()
+                .register_reader(),
+        );
+        self.points = Some(
+            world
+                .fetch_mut::<EventChannel<PointEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(
+        &mut self,
+        (contacts, points, timeline, rules, mut score, rhythm_text, mut ui_text): Self::SystemData,
+    ) {
+        let contacts = contacts.read(self.contacts.as_mut().expect("setup was not called"));
+        let points = points.read(self.points.as_mut().expect("setup was not called"));
+        let windows = match &rules.rhythm {
+            Some(windows) => windows,
+            None => return,
+        };
+
+        let judgements = contacts
+            .map(
+                |contact| match timeline.nearest_beat(contact.track, contact.time) {
+                    Some(beat) => windows.judge(contact.time - beat),
+                    None => Judgement::Miss,
+                },
+            )
+            .chain(points.map(|_| Judgement::Miss))
+            .collect::<Vec<_>>();
+        if judgements.is_empty() {
+            return;
+        }
+        for judgement in judgements {
+            score.record(judgement);
+        }
+
+        if let Some(rhythm_text) = rhythm_text {
+            if let Some(text) = ui_text.get_mut(rhythm_text.judgement) {
+                text.text = match score.last {
+                    Some(judgement) if score.combo > 1 => {
+                        format!("{:?} x{}", judgement, score.combo)
+                    }
+                    Some(judgement) => format!("{:?}", judgement),
+                    None => String::new(),
+                };
+            }
+        }
+    }
+}
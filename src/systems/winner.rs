@@ -1,15 +1,24 @@
-use crate::{audio::Sounds, Ball};
+use crate::{
+    audio::{play_score, Sounds},
+    config::GameConfig,
+    physics::{FixedTimestep, PhysicsPose},
+    score::{PointEvent, Score, ScoreText},
+    Ball, Side,
+};
 use amethyst::{
     assets::AssetStorage,
     audio::{output::Output, Source},
     core::Transform,
     derive::SystemDesc,
-    ecs::prelude::{Entity, Join, Read, ReadExpect, System, SystemData, WriteStorage},
+    ecs::prelude::{Join, Read, System, SystemData, Write, WriteStorage},
+    shrev::EventChannel,
+    ui::UiText,
 };
+use std::ops::Deref;
 
-/// This system is responsible for checking if a ball has moved into a left or
-/// a right edge. Points are distributed to the player on the other side, and
-/// the ball is reset.
+/// This system is responsible for checking if a ball has moved into an edge
+/// of the arena. The paddle on that side missed it: the miss is counted in
+/// the `Score` and sent as a `PointEvent`, and the ball is reset.
 #[derive(SystemDesc)]
 pub struct WinnerSystem;
 
@@ -17,26 +26,79 @@ impl<'s> System<'s> for WinnerSystem {
     type SystemData = (
         WriteStorage<'s, Ball>,
         WriteStorage<'s, Transform>,
+        WriteStorage<'s, PhysicsPose>,
+        WriteStorage<'s, UiText>,
+        Write<'s, Score>,
+        Option<Read<'s, ScoreText>>,
+        Write<'s, EventChannel<PointEvent>>,
+        Read<'s, FixedTimestep>,
+        Read<'s, GameConfig>,
         Read<'s, AssetStorage<Source>>,
-        ReadExpect<'s, Sounds>,
+        Option<Read<'s, Sounds>>,
         Option<Read<'s, Output>>,
     );
 
     fn run(
         &mut self,
-        (mut balls, mut transforms, storage, sounds, audio_output): Self::SystemData,
+        (
+            mut balls,
+            mut transforms,
+            mut poses,
+            mut ui_text,
+            mut score,
+            score_text,
+            mut points,
+            timestep,
+            config,
+            storage,
+            sounds,
+            audio_output,
+        ): Self::SystemData,
     ) {
-        for (ball, transform) in (&mut balls, &mut transforms).join() {
-            use crate::ARENA_WIDTH;
-
+        let arena = &config.arena;
+        score.time += timestep.step;
+        for (ball, transform, pose) in (&mut balls, &mut transforms, (&mut poses).maybe()).join() {
             let ball_x = transform.translation().x;
+            let ball_y = transform.translation().y;
 
-            let did_hit = ball_x <= ball.radius || ball_x >= ARENA_WIDTH - ball.radius;
+            let missed = if ball_x <= ball.radius {
+                Some(Side::Left)
+            } else if ball_x >= arena.width - ball.radius {
+                Some(Side::Right)
+            } else if ball_y <= ball.radius {
+                Some(Side::Top)
+            } else if ball_y >= arena.height - ball.radius {
+                Some(Side::Bottom)
+            } else {
+                None
+            };
+
+            if let Some(side) = missed {
+                score.miss(side);
+                points.single_write(PointEvent { missed: side });
 
-            if did_hit {
                 // Reset the ball.
-                ball.velocity[0] = -ball.velocity[0];
-                transform.set_translation_x(ARENA_WIDTH / 2.0);
+                match side {
+                    Side::Left | Side::Right => ball.velocity[0] = -ball.velocity[0],
+                    Side::Top | Side::Bottom => ball.velocity[1] = -ball.velocity[1],
+                }
+                transform.set_translation_x(arena.width / 2.0);
+                transform.set_translation_y(arena.height / 2.0);
+                // Jump rather than slide across the arena when interpolated.
+                if let Some(pose) = pose {
+                    *pose = PhysicsPose::at(arena.width / 2.0, arena.height / 2.0);
+                }
+
+                if let Some(score_text) = &score_text {
+                    if let Some(text) = ui_text.get_mut(score_text.of(side)) {
+                        text.text = score.misses(side).to_string();
+                    }
+                }
+                play_score(
+                    sounds.as_ref().map(|s| s.deref()),
+                    &storage,
+                    audio_output.as_ref().map(|o| o.deref()),
+                );
             }
         }
     }
@@ -0,0 +1,266 @@
+use crate::{audio::AudioOffset, beatmap::Beatmap};
+use std::cmp::Ordering;
+
+/// How close to a downbeat a beat is taken to be the same one, in seconds.
+/// Hand-made maps may round the two differently.
+const DOWNBEAT_TOLERANCE: f32 = 0.005;
+
+/// The beats of every track of the playlist, looked up by time on the track
+/// the `PlaybackClock` says is playing. Beats are found by their time rather
+/// than by counting intervals, so tempo changes stay in step, and past the
+/// end of a track the lookups carry on with the start of the track the DJ
+/// plays next, which is the same one when a single song loops. The beats
+/// are when the speakers play them, the `AudioOffset` later than in the
+/// beatmaps, so everything that follows them hears them in time.
+#[derive(Clone, Debug, Default)]
+pub struct BeatTimeline {
+    tracks: Vec<TrackBeats>,
+    /// Seconds the beats are heard after they are played.
+    offset: f32,
+}
+
+#[derive(Clone, Debug)]
+struct TrackBeats {
+    /// In order, with the offsets of the beatmap and of the audio applied.
+    beats: Vec<f32>,
+    downbeats: Vec<f32>,
+    /// Without it, nothing comes after the last beat of the track.
+    duration: Option<f32>,
+}
+
+impl BeatTimeline {
+    /// The timeline of a playlist, with the beatmaps of its tracks in the
+    /// order the DJ plays them, heard `offset` seconds late.
+    pub fn new(beatmaps: &[Beatmap], offset: &AudioOffset) -> BeatTimeline {
+        let offset = offset.seconds;
+        let sorted = |times: &mut Vec<f32>| {
+            times.retain(|time| time.is_finite());
+            times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        };
+        let tracks = beatmaps
+            .iter()
+            .map(|beatmap| {
+                let mut beats = beatmap
+                    .beat_times()
+                    .map(|beat| beat + offset)
+                    .collect::<Vec<_>>();
+                sorted(&mut beats);
+                let mut downbeats = beatmap
+                    .downbeats
+                    .iter()
+                    .flatten()
+                    .map(|downbeat| downbeat + beatmap.offset + offset)
+                    .collect::<Vec<_>>();
+                sorted(&mut downbeats);
+                TrackBeats {
+                    beats,
+                    downbeats,
+                    duration: beatmap.duration.filter(|duration| *duration > 0.0),
+                }
+            })
+            .collect();
+        BeatTimeline { tracks, offset }
+    }
+
+    /// Makes the beats heard `offset` seconds late instead, as when the
+    /// latency is calibrated again in the middle of a match.
+    pub fn set_offset(&mut self, offset: &AudioOffset) {
+        let shift = offset.seconds - self.offset;
+        for track in &mut self.tracks {
+            for time in track.beats.iter_mut().chain(&mut track.downbeats) {
+                *time += shift;
+            }
+        }
+        self.offset = offset.seconds;
+    }
+
+    /// Adds a beat found while `track` plays, after the ones it already has.
+    pub fn push_beat(&mut self, track: usize, beat: f32) {
+        let beat = beat + self.offset;
+        let count = self.tracks.len();
+        if let Some(beats) = self.tracks.get_mut(track % count.max(1)) {
+            if beats.beats.last().map_or(true, |last| beat > *last) {
+                beats.beats.push(beat);
+            }
+        }
+    }
+
+    /// Sets the length of `track` once it is known.
+    pub fn set_duration(&mut self, track: usize, duration: f32) {
+        let count = self.tracks.len();
+        if let Some(beats) = self.tracks.get_mut(track % count.max(1)) {
+            beats.duration = Some(duration).filter(|duration| *duration > 0.0);
+        }
+    }
+
+    /// Length of a track of the playlist, if it is known.
+    pub fn duration(&self, track: usize) -> Option<f32> {
+        self.track(track)?.duration
+    }
+
+    /// The first beat strictly after `time`, on the time of `track`. The
+    /// beats of the tracks after it count from the end of it.
+    pub fn next_beat(&self, track: usize, time: f32) -> Option<f32> {
+        let mut start = 0.0;
+        for index in (track..).take(self.tracks.len() + 1) {
+            let beats = self.track(index)?;
+            let after = first_after(&beats.beats, time - start);
+            if let Some(beat) = beats.beats.get(after) {
+                return Some(start + beat);
+            }
+            start += beats.duration?;
+        }
+        None
+    }
+
+    /// The last beat at or before `time`, on the time of `track`. Before the
+    /// first beat of the track, that is the last beat of the track before.
+    pub fn previous_beat(&self, track: usize, time: f32) -> Option<f32> {
+        let count = self.tracks.len();
+        let mut end = 0.0;
+        for back in 0..=count {
+            let beats = self.track(track % count.max(1) + count - back)?;
+            if back > 0 {
+                end -= beats.duration?;
+            }
+            let after = first_after(&beats.beats, time - end);
+            if after > 0 {
+                return Some(end + beats.beats[after - 1]);
+            }
+        }
+        None
+    }
+
+    /// The beat closest to `time`, before or after it.
+    pub fn nearest_beat(&self, track: usize, time: f32) -> Option<f32> {
+        match (self.previous_beat(track, time), self.next_beat(track, time)) {
+            (Some(before), Some(after)) if after - time < time - before => Some(after),
+            (Some(before), _) => Some(before),
+            (None, after) => after,
+        }
+    }
+
+    /// Whether the beat at `time`, as returned by the lookups, starts a bar.
+    /// Never for tracks without downbeats.
+    pub fn is_downbeat(&self, track: usize, time: f32) -> bool {
+        let mut start = 0.0;
+        for index in (track..).take(self.tracks.len() + 1) {
+            let beats = match self.track(index) {
+                Some(beats) => beats,
+                None => return false,
+            };
+            let duration = beats.duration.unwrap_or(std::f32::INFINITY);
+            if time - start < duration {
+                let local = time - start;
+                return beats
+                    .downbeats
+                    .iter()
+                    .any(|downbeat| (downbeat - local).abs() < DOWNBEAT_TOLERANCE);
+            }
+            start += duration;
+        }
+        false
+    }
+
+    /// The beats of the track the DJ plays at `track`, counting every loop
+    /// of the playlist.
+    fn track(&self, track: usize) -> Option<&TrackBeats> {
+        if self.tracks.is_empty() {
+            None
+        } else {
+            self.tracks.get(track % self.tracks.len())
+        }
+    }
+}
+
+/// Index of the first of the sorted `times` strictly after `time`.
+fn first_after(times: &[f32], time: f32) -> usize {
+    match times.binary_search_by(|probe| {
+        probe
+            .partial_cmp(&time)
+            .unwrap_or(Ordering::Less)
+            .then(Ordering::Less)
+    }) {
+        Ok(index) | Err(index) => index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beatmap(beats: &[f32], duration: Option<f32>) -> Beatmap {
+        Beatmap {
+            audio: String::new(),
+            bpm: 120.0,
+            offset: 0.0,
+            beats: beats.to_vec(),
+            downbeats: None,
+            sections: vec![],
+            duration,
+        }
+    }
+
+    /// Two tracks, of 2 and 1 seconds.
+    fn playlist() -> BeatTimeline {
+        BeatTimeline::new(
+            &[beatmap(&[0.5, 1.5], Some(2.0)), beatmap(&[0.25], Some(1.0))],
+            &AudioOffset::default(),
+        )
+    }
+
+    #[test]
+    fn next_beat_within_a_track() {
+        let timeline = playlist();
+        assert_eq!(timeline.next_beat(0, 0.0), Some(0.5));
+        assert_eq!(timeline.next_beat(0, 0.5), Some(1.5));
+    }
+
+    #[test]
+    fn next_beat_carries_on_into_the_next_track_and_loops() {
+        let timeline = playlist();
+        assert_eq!(timeline.next_beat(0, 1.6), Some(2.25));
+        // The last track loops back to the first one.
+        assert_eq!(timeline.next_beat(1, 0.5), Some(1.5));
+        assert_eq!(timeline.next_beat(1, 1.6), Some(2.5));
+    }
+
+    #[test]
+    fn previous_beat_goes_back_into_the_track_before() {
+        let timeline = playlist();
+        assert_eq!(timeline.previous_beat(0, 1.5), Some(1.5));
+        assert_eq!(timeline.previous_beat(0, 1.0), Some(0.5));
+        assert_eq!(timeline.previous_beat(0, 0.2), Some(-0.75));
+        assert_eq!(timeline.previous_beat(1, 0.1), Some(-0.5));
+    }
+
+    #[test]
+    fn beats_stop_without_a_duration() {
+        let timeline = BeatTimeline::new(&[beatmap(&[0.5, 1.5], None)], &AudioOffset::default());
+        assert_eq!(timeline.next_beat(0, 1.5), None);
+        assert_eq!(timeline.previous_beat(0, 0.2), None);
+    }
+
+    #[test]
+    fn beats_are_heard_late_by_the_offset() {
+        let mut timeline = BeatTimeline::new(
+            &[beatmap(&[0.5, 1.5], Some(2.0))],
+            &AudioOffset { seconds: 0.25 },
+        );
+        assert_eq!(timeline.next_beat(0, 0.0), Some(0.75));
+        timeline.push_beat(0, 1.75);
+        assert_eq!(timeline.next_beat(0, 1.75), Some(2.0));
+    }
+
+    #[test]
+    fn set_offset_moves_the_beats() {
+        let mut timeline = BeatTimeline::new(
+            &[beatmap(&[0.5, 1.5], Some(2.0))],
+            &AudioOffset { seconds: 0.25 },
+        );
+        timeline.set_offset(&AudioOffset { seconds: 0.125 });
+        assert_eq!(timeline.next_beat(0, 0.0), Some(0.625));
+        timeline.push_beat(0, 1.75);
+        assert_eq!(timeline.next_beat(0, 1.75), Some(1.875));
+    }
+}